@@ -0,0 +1,345 @@
+use super::config::Config;
+use super::deck;
+use super::dictionary::{Dictionary, DICTIONARY_FILE, DICTIONARY_INDEX_FILE};
+use super::file_io::{cache_dir, data_dir, read_compressed_dict};
+use super::game::{self, RandomWeights, Session};
+use super::models::{DictObject, Pronouncation, Script, ToBaseModel, Voice};
+use super::stats::{Direction, History};
+use super::tone_archive::{self, TONE_ARCHIVE_FILE, TONE_INDEX_FILE, TONE_TAR_FILE};
+use super::utils::StyledWrite;
+use super::{DICTIONARY, PHRASES_FILE, SENTENCES_FILE, WORDS_FILE};
+use anyhow::{anyhow, Error};
+use clap::{Parser, Subcommand, ValueEnum};
+use console::{style, Term};
+use std::path::{Path, PathBuf};
+
+/// Learn Chinese words, phrases, sentences and tones in the terminal.
+///
+/// Starts the interactive menu when no subcommand is given.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// Directory of the decks, the dictionary and the tone archive
+    #[arg(long, global = true, value_name = "DIR")]
+    pub data_dir: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Start a drill straight away
+    Play {
+        #[arg(short, long, value_enum, default_value_t = ModeArg::Words)]
+        mode: ModeArg,
+        /// Defaults to both directions, in the ratio set in the config
+        #[arg(short, long, value_enum)]
+        direction: Option<DirectionArg>,
+        /// Stop after this many rounds, defaults to the session length in the config
+        #[arg(short, long)]
+        count: Option<usize>,
+    },
+    /// Look up a word in the dictionary by characters, pinyin or English
+    Lookup {
+        #[arg(required = true)]
+        query: Vec<String>,
+        /// Play the pronunciation of the first match
+        #[arg(short, long)]
+        play: bool,
+    },
+    /// Show the statistics of all rounds played
+    Stats,
+    /// Manage the word lists
+    Deck {
+        #[command(subcommand)]
+        command: DeckCommand,
+    },
+    /// Build the dictionary and tone archive indexes in the cache directory
+    Index,
+    /// Show or change the settings
+    Config {
+        #[command(subcommand)]
+        command: Option<ConfigCommand>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DeckCommand {
+    /// Check deck files for mistakes, the built-in decks by default
+    Validate { paths: Vec<PathBuf> },
+}
+
+impl Command {
+    /// Whether the command reads the decks, the dictionary or the tone archive.
+    pub const fn needs_data(&self) -> bool {
+        match self {
+            Self::Play { .. } | Self::Lookup { .. } | Self::Index => true,
+            Self::Deck {
+                command: DeckCommand::Validate { paths },
+            } => paths.is_empty(),
+            Self::Stats | Self::Config { .. } => false,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Show the settings in use, the default
+    Show,
+    /// Change a setting, e.g. `script simplified` or `voices FV1,MV2`
+    Set { key: String, value: String },
+    /// Restore the default settings
+    Reset,
+    /// Print the path of the config file
+    Path,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ModeArg {
+    Words,
+    Phrases,
+    Sentences,
+    Tones,
+    Random,
+}
+
+impl ModeArg {
+    pub fn mode(self, weights: &RandomWeights) -> game::Mode {
+        match self {
+            Self::Words => game::Mode::Words,
+            Self::Phrases => game::Mode::Phrases,
+            Self::Sentences => game::Mode::Sentences,
+            Self::Tones => game::Mode::Tones,
+            Self::Random => game::Mode::Random(weights.clone()),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DirectionArg {
+    /// Chinese to English
+    #[value(name = "zh-en")]
+    ZhEn,
+    /// English to Chinese
+    #[value(name = "en-zh")]
+    EnZh,
+    /// Both directions at random
+    Both,
+}
+
+impl DirectionArg {
+    pub const fn direction(self) -> Option<Direction> {
+        match self {
+            Self::ZhEn => Some(Direction::ChineseToEnglish),
+            Self::EnZh => Some(Direction::EnglishToChinese),
+            Self::Both => None,
+        }
+    }
+}
+
+/// Most dictionary matches listed at once.
+const MAX_MATCHES: usize = 20;
+
+/// Prints the dictionary entries matching the characters, pinyin or English,
+/// the likeliest first, and plays the first one with `play`.
+pub fn lookup(terminal: &mut Term, query: &str, play: bool, config: &Config) -> Result<(), Error> {
+    let matches = DICTIONARY.search(query, config.script);
+    let Some(first) = matches.first() else {
+        return Err(anyhow!("No dictionary entry for {}", query.trim()));
+    };
+    print_matches(terminal, &matches)?;
+    if play {
+        match audio_session(config) {
+            Ok(session) => play_entry(terminal, first, config.script, &session)?,
+            Err(reason) => terminal.write_styled_line(style(reason).yellow())?,
+        }
+    }
+    Ok(())
+}
+
+/// Searches the dictionary until an empty line is entered, playing a match
+/// when its number is typed if audio is on.
+pub fn browse(terminal: &mut Term, config: &Config) -> Result<(), Error> {
+    let session = audio_session(config).ok();
+    terminal.write_styled_line(
+        style(if session.is_some() {
+            "Search by characters, pinyin or English. Type the number of a match \
+            to hear it, or press Enter to go back."
+        } else {
+            "Search by characters, pinyin or English, or press Enter to go back."
+        })
+        .dim(),
+    )?;
+    let mut shown: Vec<DictObject> = Vec::new();
+    loop {
+        terminal.write_styled("search> ")?;
+        let input = terminal.read_line()?;
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(());
+        }
+        let picked = input
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| shown.get(number.checked_sub(1)?));
+        if let (Some(entry), Some(session)) = (picked, &session) {
+            play_entry(terminal, entry, config.script, session)?;
+            continue;
+        }
+        let matches = DICTIONARY.search(input, config.script);
+        if matches.is_empty() {
+            terminal
+                .write_styled_line(style(format!("No dictionary entry for {input}")).yellow())?;
+        }
+        print_matches(terminal, &matches)?;
+        shown = matches.into_iter().take(MAX_MATCHES).cloned().collect();
+    }
+}
+
+fn print_matches(terminal: &mut Term, matches: &[&DictObject]) -> Result<(), Error> {
+    for (i, entry) in matches.iter().take(MAX_MATCHES).enumerate() {
+        terminal.write_styled_line(format!(
+            "{} {}",
+            style(format!("{}.", i + 1)).dim(),
+            entry.styled()
+        ))?;
+    }
+    if matches.len() > MAX_MATCHES {
+        terminal.write_styled_line(
+            style(format!(
+                "... and {} more, search for something longer to narrow them down.",
+                matches.len() - MAX_MATCHES
+            ))
+            .dim(),
+        )?;
+    }
+    Ok(())
+}
+
+/// The session dictionary entries are played with, or why they cannot be played.
+fn audio_session(config: &Config) -> Result<Session, String> {
+    let session = config.session();
+    if !session.audio {
+        return Err("Audio is turned off.".to_string());
+    }
+    Pronouncation::check_available(&session.speaker, false)
+        .map_err(|e| format!("Could not play audio: {e}"))?;
+    Ok(session)
+}
+
+/// Plays the pronunciation of a dictionary entry, reporting when it cannot.
+fn play_entry(
+    terminal: &mut Term,
+    entry: &DictObject,
+    script: Script,
+    session: &Session,
+) -> Result<(), Error> {
+    let mut word = entry.to_base_model();
+    word.set_script(script);
+    let voice = Voice::random_from(&session.voices);
+    let pronounce = Pronouncation::create_from(&mut word, voice, &session.speaker);
+    if let Err(e) = pronounce.play_all() {
+        terminal.write_styled_line(style(format!("Could not play audio: {e}")).yellow())?;
+    }
+    Ok(())
+}
+
+pub fn stats(terminal: &Term) -> Result<(), Error> {
+    terminal.write_line(&History::load_default().report())?;
+    Ok(())
+}
+
+/// Builds the indexes the dictionary and the tone clips are read through,
+/// so they are not built the first time they are needed.
+pub fn build_indexes(terminal: &mut Term) -> Result<(), Error> {
+    let (data_dir, cache_dir) = (data_dir()?, cache_dir());
+    let built = |terminal: &mut Term, path: &Path| {
+        terminal.write_styled_line(format!("{} {}", style("✓").green(), path.display()))
+    };
+
+    let index_path = cache_dir.join(DICTIONARY_INDEX_FILE);
+    Dictionary::new(read_compressed_dict(&data_dir.join(DICTIONARY_FILE))?)
+        .write_index(&index_path)?;
+    built(terminal, &index_path)?;
+
+    let archive_path = data_dir.join(TONE_ARCHIVE_FILE);
+    if !archive_path.exists() {
+        terminal.write_styled_line(
+            style(format!("No tone archive at {}", archive_path.display())).yellow(),
+        )?;
+        return Ok(());
+    }
+    let index_path = cache_dir.join(TONE_INDEX_FILE);
+    tone_archive::build_index(&archive_path, &cache_dir.join(TONE_TAR_FILE), &index_path)?;
+    built(terminal, &index_path)?;
+    Ok(())
+}
+
+/// Validates the given deck files and fails if any of them has problems.
+pub fn validate_decks(terminal: &mut Term, paths: &[PathBuf]) -> Result<(), Error> {
+    let paths = if paths.is_empty() {
+        let dir = data_dir()?;
+        vec![
+            dir.join(WORDS_FILE),
+            dir.join(PHRASES_FILE),
+            dir.join(SENTENCES_FILE),
+        ]
+    } else {
+        paths.to_vec()
+    };
+
+    let mut problem_count = 0;
+    for path in &paths {
+        let problems = deck::validate(path);
+        if problems.is_empty() {
+            terminal.write_styled_line(format!("{} {}", style("✓").green(), path.display()))?;
+            continue;
+        }
+        terminal.write_styled_line(format!("{} {}", style("✗").red(), path.display()))?;
+        for problem in &problems {
+            terminal.write_line(&format!("  {problem}"))?;
+        }
+        problem_count += problems.len();
+    }
+
+    if problem_count > 0 {
+        return Err(anyhow!("Found {} problems in the decks", problem_count));
+    }
+    Ok(())
+}
+
+/// Shows or changes the settings in the config file.
+pub fn config(terminal: &mut Term, command: ConfigCommand, config: &Config) -> Result<(), Error> {
+    let path = Config::path();
+    match command {
+        ConfigCommand::Show => {
+            let saved = serde_json::to_value(Config::read(&path)?)?;
+            terminal.write_styled_line(style(path.display()).dim())?;
+            for (key, value) in serde_json::to_value(config)?
+                .as_object()
+                .into_iter()
+                .flatten()
+            {
+                let overridden = if saved.get(key) == Some(value) {
+                    String::new()
+                } else {
+                    style(" (from the environment)").dim().to_string()
+                };
+                terminal
+                    .write_styled_line(format!("{} = {value}{overridden}", style(key).cyan()))?;
+            }
+        }
+        ConfigCommand::Set { key, value } => {
+            let mut saved = Config::read(&path)?;
+            saved.set(&key, &value)?;
+            saved.save(&path)?;
+            terminal.write_line(&format!("Saved {key} to {}", path.display()))?;
+        }
+        ConfigCommand::Reset => {
+            Config::default().save(&path)?;
+            terminal.write_line(&format!("Reset {}", path.display()))?;
+        }
+        ConfigCommand::Path => terminal.write_line(&path.display().to_string())?,
+    }
+    Ok(())
+}
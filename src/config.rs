@@ -0,0 +1,332 @@
+use super::file_io::config_dir;
+use super::game::{AnswerOptions, RandomWeights, Session};
+use super::models::{Script, Voice};
+use super::tts::{Engine, Speaker, TtsConfig};
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Learner preferences, read from `config.json` in the config directory.
+///
+/// Every setting can be overridden with an environment variable, e.g. from
+/// the `.env` file, see [`Config::apply_env`].
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(default, deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Config {
+    /// Share of translation rounds asked from Chinese to English, in percent
+    pub chinese_to_english_percent: u8,
+    /// Play the pronunciation during rounds
+    pub audio: bool,
+    /// Tone archive voices picked from at random
+    pub voices: Vec<Voice>,
+    pub script: Script,
+    pub strict_tones: bool,
+    pub require_characters: bool,
+    pub notes_on_demand: bool,
+    /// Show the tones as spoken, e.g. 你好 ní hǎo, next to the dictionary tones
+    pub spoken_tones: bool,
+    /// Show zhuyin (Bopomofo) instead of pinyin, e.g. 你好 ㄋㄧˇ ㄏㄠˇ
+    pub zhuyin: bool,
+    /// Rounds per drill, unlimited when `None`
+    pub session_length: Option<usize>,
+    pub random_weights: RandomWeights,
+    /// Pronunciation of what the tone archive has no recordings of
+    pub tts: TtsConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            chinese_to_english_percent: 50,
+            audio: true,
+            voices: Voice::ARCHIVE.to_vec(),
+            script: Script::default(),
+            strict_tones: false,
+            require_characters: false,
+            notes_on_demand: false,
+            spoken_tones: false,
+            zhuyin: false,
+            session_length: None,
+            random_weights: RandomWeights::default(),
+            tts: TtsConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        config_dir().join("config.json")
+    }
+
+    /// Loads the config file with the environment overrides applied, falling
+    /// back to the defaults if the file is invalid. Invalid overrides are ignored.
+    pub fn load() -> Self {
+        let path = Self::path();
+        let mut config = Self::read(&path).unwrap_or_else(|e| {
+            log::warn!("Invalid config {}: {}. Using defaults.", path.display(), e);
+            Self::default()
+        });
+        config.apply_env();
+        config
+    }
+
+    /// Reads the config file, the defaults if it does not exist.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let config: Self = serde_json::from_slice(&fs::read(path)?)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        self.validate()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.chinese_to_english_percent > 100 {
+            return Err(anyhow!("chinese_to_english_percent must be at most 100"));
+        }
+        if self.voices.is_empty() {
+            return Err(anyhow!("voices must not be empty"));
+        }
+        if let Some(voice) = self.voices.iter().find(|v| !Voice::ARCHIVE.contains(v)) {
+            return Err(anyhow!("{} is not a tone archive voice", voice));
+        }
+        if self.session_length == Some(0) {
+            return Err(anyhow!("session_length must be greater than zero"));
+        }
+        self.random_weights.validate()?;
+        self.tts.validate()
+    }
+
+    /// Overrides settings from the `LINGUA_*` environment variables, e.g.
+    /// `LINGUA_SCRIPT=simplified` or `LINGUA_VOICES=FV1,MV2`.
+    fn apply_env(&mut self) {
+        self.override_from_env(
+            "LINGUA_CHINESE_TO_ENGLISH_PERCENT",
+            |c| &mut c.chinese_to_english_percent,
+            |v| v.parse().ok(),
+        );
+        self.override_from_env("LINGUA_AUDIO", |c| &mut c.audio, parse_flag);
+        self.override_from_env(
+            "LINGUA_VOICES",
+            |c| &mut c.voices,
+            |v| v.split(',').map(Voice::parse).collect(),
+        );
+        self.override_from_env("LINGUA_SCRIPT", |c| &mut c.script, Script::parse);
+        self.override_from_env("LINGUA_STRICT_TONES", |c| &mut c.strict_tones, parse_flag);
+        self.override_from_env(
+            "LINGUA_REQUIRE_CHARACTERS",
+            |c| &mut c.require_characters,
+            parse_flag,
+        );
+        self.override_from_env(
+            "LINGUA_NOTES_ON_DEMAND",
+            |c| &mut c.notes_on_demand,
+            parse_flag,
+        );
+        self.override_from_env("LINGUA_SPOKEN_TONES", |c| &mut c.spoken_tones, parse_flag);
+        self.override_from_env("LINGUA_ZHUYIN", |c| &mut c.zhuyin, parse_flag);
+        self.override_from_env(
+            "LINGUA_SESSION_LENGTH",
+            |c| &mut c.session_length,
+            |v| match v.to_lowercase().as_str() {
+                "" | "none" | "unlimited" => Some(None),
+                v => v.parse().ok().map(Some),
+            },
+        );
+        self.override_from_env(
+            "LINGUA_RANDOM_WEIGHTS",
+            |c| &mut c.random_weights,
+            |v| {
+                RandomWeights::parse(v)
+                    .map_err(|e| log::warn!("{}", e))
+                    .ok()
+            },
+        );
+        self.override_from_env("LINGUA_TTS_ENGINE", |c| &mut c.tts.engine, Engine::parse);
+        self.override_from_env(
+            "LINGUA_TTS_URL",
+            |c| &mut c.tts.url,
+            |v| Some(v.to_string()),
+        );
+    }
+
+    /// Sets the setting from the environment variable `name` if it is set,
+    /// ignoring it if it cannot be parsed or makes the config invalid.
+    fn override_from_env<T>(
+        &mut self,
+        name: &str,
+        setting: fn(&mut Self) -> &mut T,
+        parse: impl Fn(&str) -> Option<T>,
+    ) {
+        let Ok(value) = env::var(name) else {
+            return;
+        };
+        let Some(parsed) = parse(value.trim()) else {
+            log::warn!("Ignoring invalid {}: {}", name, value);
+            return;
+        };
+        let mut config = self.clone();
+        *setting(&mut config) = parsed;
+        match config.validate() {
+            Ok(()) => *self = config,
+            Err(e) => log::warn!("Ignoring invalid {}: {}", name, e),
+        }
+    }
+
+    /// Names of all settings, as used by [`Config::set`].
+    pub fn keys(&self) -> Vec<String> {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|value| value.as_object().map(|o| o.keys().cloned().collect()))
+            .unwrap_or_default()
+    }
+
+    /// Sets a setting from its text form, e.g. `script simplified`,
+    /// `voices FV1,MV2` or `random_weights.tones 0`.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        let mut json = serde_json::to_value(&*self)?;
+        let field = json
+            .pointer_mut(&format!("/{}", key.replace('.', "/")))
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown setting `{}`. Settings: {}",
+                    key,
+                    self.keys().join(", ")
+                )
+            })?;
+        *field = serde_json::from_str(value).unwrap_or_else(|_| {
+            if field.is_array() {
+                value
+                    .split(',')
+                    .map(|item| Value::String(item.trim().to_string()))
+                    .collect()
+            } else {
+                Value::String(value.to_string())
+            }
+        });
+
+        let config: Self = serde_json::from_value(json)
+            .map_err(|e| anyhow!("Invalid value for `{}`: {}", key, e))?;
+        config.validate()?;
+        *self = config;
+        Ok(())
+    }
+
+    pub const fn answer_options(&self) -> AnswerOptions {
+        AnswerOptions {
+            strict_tones: self.strict_tones,
+            require_characters: self.require_characters,
+            notes_on_demand: self.notes_on_demand,
+            spoken_tones: self.spoken_tones,
+            zhuyin: self.zhuyin,
+            script: self.script,
+        }
+    }
+
+    pub fn session(&self) -> Session {
+        Session {
+            direction: None,
+            chinese_to_english_percent: self.chinese_to_english_percent,
+            count: self.session_length,
+            audio: self.audio,
+            voices: self.voices.clone(),
+            speaker: Arc::new(Speaker::new(&self.tts)),
+        }
+    }
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set() {
+        let mut config = Config::default();
+        config.set("script", "simplified").unwrap();
+        config.set("voices", "FV1, MV2").unwrap();
+        config.set("session_length", "20").unwrap();
+        config.set("random_weights.tones", "0").unwrap();
+        config.set("audio", "false").unwrap();
+        config.set("tts.url", "http://localhost:8080/tts").unwrap();
+        assert_eq!(config.script, Script::Simplified);
+        assert_eq!(config.voices, vec![Voice::FV1, Voice::MV2]);
+        assert_eq!(config.session_length, Some(20));
+        assert!(!config.audio);
+        assert_eq!(config.tts.url, "http://localhost:8080/tts");
+        assert_eq!(
+            config.random_weights,
+            RandomWeights::parse("words=4,phrases=3,sentences=2").unwrap()
+        );
+
+        config.set("session_length", "null").unwrap();
+        assert_eq!(config.session_length, None);
+
+        let unchanged = config.clone();
+        assert!(config.set("colour", "red").is_err());
+        assert!(config.set("script", "cursive").is_err());
+        assert!(config.set("chinese_to_english_percent", "101").is_err());
+        assert!(config.set("voices", "TTS").is_err());
+        assert!(config.set("tts.engine", "command").is_err());
+        assert_eq!(config, unchanged);
+    }
+
+    #[test]
+    fn test_save_and_read() {
+        let path = std::env::temp_dir().join(format!(
+            "lingua_test_{}_config/config.json",
+            std::process::id()
+        ));
+        assert_eq!(Config::read(&path).unwrap(), Config::default());
+
+        let mut config = Config::default();
+        config.set("strict_tones", "true").unwrap();
+        config.save(&path).unwrap();
+        assert_eq!(Config::read(&path).unwrap(), config);
+
+        fs::write(&path, r#"{"script": "both"}"#).unwrap();
+        assert_eq!(Config::read(&path).unwrap().script, Script::Both);
+        fs::write(&path, r#"{"colour": "red"}"#).unwrap();
+        assert!(Config::read(&path).is_err());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_apply_env() {
+        let mut config = Config::default();
+        config.set("strict_tones", "true").unwrap();
+        env::set_var("LINGUA_SCRIPT", "simplified");
+        env::set_var("LINGUA_SESSION_LENGTH", "0");
+        env::set_var("LINGUA_CHINESE_TO_ENGLISH_PERCENT", "many");
+        config.apply_env();
+        env::remove_var("LINGUA_SCRIPT");
+        env::remove_var("LINGUA_SESSION_LENGTH");
+        env::remove_var("LINGUA_CHINESE_TO_ENGLISH_PERCENT");
+
+        assert_eq!(config.script, Script::Simplified);
+        assert_eq!(config.session_length, None);
+        assert_eq!(config.chinese_to_english_percent, 50);
+        assert!(config.strict_tones);
+    }
+}
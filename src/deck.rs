@@ -0,0 +1,166 @@
+use super::file_io::load_from_file;
+use super::models::{BaseModelDto, ToBaseModel};
+use super::pinyin;
+use super::utils::string::is_hanzi;
+use super::DICTIONARY;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Returns a description of every problem found in the deck file at `path`.
+pub fn validate(path: &Path) -> Vec<String> {
+    match load_from_file::<BaseModelDto>(path) {
+        Ok(models) => validate_models(&models),
+        Err(e) => vec![format!("Could not read deck: {e}")],
+    }
+}
+
+fn validate_models(models: &[BaseModelDto]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut seen = HashMap::new();
+    for (i, model) in models.iter().enumerate() {
+        let entry = i + 1;
+        let name = model.traditional.join(",");
+        let mut problem = |text: String| problems.push(format!("Entry {entry} ({name}): {text}"));
+
+        if model.traditional.is_empty() {
+            problem("has no traditional characters".to_string());
+        }
+        if model.traditional.len() == model.simplified.len() {
+            for (traditional, simplified) in model.traditional.iter().zip(&*model.simplified) {
+                if traditional.chars().count() != simplified.chars().count() {
+                    problem(format!(
+                        "`{traditional}` and `{simplified}` have a different number of characters"
+                    ));
+                }
+            }
+        } else {
+            problem(format!(
+                "has {} traditional but {} simplified words",
+                model.traditional.len(),
+                model.simplified.len()
+            ));
+        }
+        if model
+            .english
+            .iter()
+            .all(|english| english.trim().is_empty())
+        {
+            problem("has no English translation".to_string());
+        }
+        if let Some(pinyin) = &model.pinyin {
+            if pinyin.len() == model.traditional.len() {
+                for (word, pinyin) in model.traditional.iter().zip(&**pinyin) {
+                    let characters = word.chars().filter(|c| is_hanzi(*c)).count();
+                    match pinyin::segment(pinyin) {
+                        None => problem(format!("`{pinyin}` is not pinyin")),
+                        Some(syllables) if syllables.len() != characters => problem(format!(
+                            "pinyin `{pinyin}` does not match the {characters} characters of `{word}`"
+                        )),
+                        Some(_) => {}
+                    }
+                }
+            } else {
+                problem(format!(
+                    "has {} traditional words but {} pinyin",
+                    model.traditional.len(),
+                    pinyin.len()
+                ));
+            }
+        } else {
+            for word in model.traditional.iter().filter(|w| !has_pinyin(w)) {
+                problem(format!("no pinyin found for `{word}`"));
+            }
+        }
+        if let Some(first) = seen.insert(model.to_base_model().key(), entry) {
+            problem(format!("duplicate of entry {first}"));
+        }
+    }
+    problems
+}
+
+/// A word has pinyin if the dictionary knows the whole word or each of its characters.
+fn has_pinyin(word: &str) -> bool {
+    DICTIONARY.pinyin(&word).is_some()
+        || word
+            .chars()
+            .filter(|c| is_hanzi(*c))
+            .all(|c| DICTIONARY.pinyin(&c).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boxed(words: &[&str]) -> Box<[Box<str>]> {
+        words.iter().map(|w| (*w).into()).collect()
+    }
+
+    fn model(traditional: &[&str], simplified: &[&str], english: &[&str]) -> BaseModelDto {
+        BaseModelDto {
+            traditional: boxed(traditional),
+            simplified: boxed(simplified),
+            english: boxed(english),
+            notes: None,
+            pinyin: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_models() {
+        let models = [
+            model(&["你好"], &["你好"], &["hello"]),
+            model(
+                &["今天天氣很好。"],
+                &["今天天气很好。"],
+                &["The weather is nice today."],
+            ),
+            model(&["我愛你"], &["我爱你", "你"], &["I love you"]),
+            model(&["天氣"], &["天"], &[" "]),
+            model(&["你好"], &["你好"], &["hi"]),
+            BaseModelDto {
+                pinyin: Some(boxed(&["yín háng"])),
+                ..model(&["銀行"], &["银行"], &["bank"])
+            },
+            BaseModelDto {
+                pinyin: Some(boxed(&["háng"])),
+                ..model(&["行人"], &["行人"], &["pedestrian"])
+            },
+            BaseModelDto {
+                pinyin: Some(boxed(&["xíng", "háng"])),
+                ..model(&["行"], &["行"], &["to walk"])
+            },
+            BaseModelDto {
+                pinyin: Some(boxed(&["chang2jiang1"])),
+                ..model(&["長江"], &["长江"], &["Yangtze"])
+            },
+            BaseModelDto {
+                pinyin: Some(boxed(&["hello"])),
+                ..model(&["您好"], &["您好"], &["hello"])
+            },
+            model(&["了"], &["了"], &["already"]),
+            BaseModelDto {
+                pinyin: Some(boxed(&["liǎo"])),
+                ..model(&["了"], &["了"], &["to finish"])
+            },
+            BaseModelDto {
+                pinyin: Some(boxed(&["liao3"])),
+                ..model(&["了"], &["了"], &["finished"])
+            },
+        ];
+        assert_eq!(
+            validate_models(&models),
+            vec![
+                "Entry 3 (我愛你): has 1 traditional but 2 simplified words",
+                "Entry 4 (天氣): `天氣` and `天` have a different number of characters",
+                "Entry 4 (天氣): has no English translation",
+                "Entry 5 (你好): duplicate of entry 1",
+                "Entry 7 (行人): pinyin `háng` does not match the 2 characters of `行人`",
+                "Entry 8 (行): has 1 traditional words but 2 pinyin",
+                "Entry 10 (您好): `hello` is not pinyin",
+                "Entry 13 (了): duplicate of entry 12",
+            ]
+        );
+        assert!(validate(Path::new("files/words.json")).is_empty());
+        assert_eq!(validate(Path::new("missing.json")).len(), 1);
+    }
+}
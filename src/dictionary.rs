@@ -0,0 +1,655 @@
+use super::file_io::{is_up_to_date, read_compressed_dict};
+use super::models::{DictObject, Script};
+use super::pinyin;
+use super::utils::string::is_hanzi;
+use anyhow::{anyhow, Error};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+pub const DICTIONARY_FILE: &str = "dictionary.json.zlib";
+pub const DICTIONARY_INDEX_FILE: &str = "dictionary.idx";
+
+const INDEX_MAGIC: &[u8; 4] = b"LDIX";
+const INDEX_VERSION: u32 = 1;
+
+/// Longest word in characters looked up when segmenting text.
+const MAX_WORD_LENGTH: usize = 8;
+
+/// Most frequent readings of polyphonic characters, ranked first where the
+/// dictionary order would pick a rarer one, e.g. 得 as the particle `de` and
+/// not `dé`. Numbered as by [`pinyin::to_numbered`].
+const COMMON_READINGS: [(char, &str); 24] = [
+    ('得', "de"),
+    ('著', "zhe"),
+    ('着', "zhe"),
+    ('行', "xing2"),
+    ('覺', "jue2"),
+    ('觉', "jue2"),
+    ('重', "zhong4"),
+    ('為', "wei4"),
+    ('为', "wei4"),
+    ('嗎', "ma"),
+    ('吗', "ma"),
+    ('吧', "ba"),
+    ('說', "shuo1"),
+    ('说', "shuo1"),
+    ('會', "hui4"),
+    ('会', "hui4"),
+    ('要', "yao4"),
+    ('看', "kan4"),
+    ('轉', "zhuan3"),
+    ('转', "zhuan3"),
+    ('乾', "gan1"),
+    ('干', "gan1"),
+    ('更', "geng4"),
+    ('差', "cha4"),
+];
+
+/// A piece of a sentence, see [`Dictionary::tokenize`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Token {
+    /// A dictionary word or a single character, with its pinyin syllables if
+    /// it has an entry
+    Word {
+        text: String,
+        pinyin: Option<String>,
+    },
+    /// Punctuation, latin letters or numbers
+    Other(String),
+}
+
+/// In-memory dictionary with sorted indexes on the traditional and simplified keys.
+///
+/// Entries keep the order of the source file within a key.
+pub struct Dictionary {
+    entries: Box<[DictObject]>,
+    by_traditional: Box<[u32]>,
+    by_simplified: Box<[u32]>,
+}
+
+impl Dictionary {
+    pub fn new(entries: Box<[DictObject]>) -> Self {
+        let sorted_by = |key: fn(&DictObject) -> &str| {
+            let mut index = (0..entries.len())
+                .filter_map(|i| u32::try_from(i).ok())
+                .collect::<Vec<_>>();
+            index.sort_by(|a, b| key(&entries[*a as usize]).cmp(key(&entries[*b as usize])));
+            index.into_boxed_slice()
+        };
+        let by_traditional = sorted_by(|e| &e.traditional);
+        let by_simplified = sorted_by(|e| &e.simplified);
+        Self {
+            entries,
+            by_traditional,
+            by_simplified,
+        }
+    }
+
+    /// Loads the prebuilt index from the data directory or the index built in
+    /// `cache_dir`, rebuilding it from the compressed dictionary if both are
+    /// missing or older than the dictionary.
+    ///
+    /// The rebuilt index is written to `cache_dir`, as the data directory may
+    /// be read-only.
+    pub fn load(data_dir: &Path, cache_dir: &Path) -> Result<Self, Error> {
+        let path = data_dir.join(DICTIONARY_FILE);
+        let (prebuilt_path, index_path) = (
+            data_dir.join(DICTIONARY_INDEX_FILE),
+            cache_dir.join(DICTIONARY_INDEX_FILE),
+        );
+        for index_path in [&prebuilt_path, &index_path] {
+            if is_up_to_date(index_path, &path) {
+                match Self::read_index(index_path) {
+                    Ok(dictionary) => return Ok(dictionary),
+                    Err(e) => log::warn!("Could not read dictionary index: {}", e),
+                }
+            }
+        }
+
+        let dictionary = Self::new(read_compressed_dict(&path)?);
+        if let Err(e) = dictionary.write_index(&index_path) {
+            log::warn!("Could not write dictionary index: {}", e);
+        }
+        Ok(dictionary)
+    }
+
+    fn equal_range<'a>(
+        &'a self,
+        index: &'a [u32],
+        key: &'a str,
+        field: fn(&DictObject) -> &str,
+    ) -> impl Iterator<Item = &'a DictObject> {
+        let start = index.partition_point(|i| field(&self.entries[*i as usize]) < key);
+        index[start..]
+            .iter()
+            .map(|i| &self.entries[*i as usize])
+            .take_while(move |entry| field(entry) == key)
+    }
+
+    /// Returns all entries whose traditional form is `key`, in source order.
+    pub fn lookup_traditional<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a DictObject> {
+        self.equal_range(&self.by_traditional, key, |e| &e.traditional)
+    }
+
+    /// Returns all entries whose simplified form is `key`, in source order.
+    pub fn lookup_simplified<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a DictObject> {
+        self.equal_range(&self.by_simplified, key, |e| &e.simplified)
+    }
+
+    /// Returns all entries matching the traditional or simplified characters.
+    pub fn lookup<'a>(&'a self, text: &'a str) -> Vec<&'a DictObject> {
+        let mut entries = self.lookup_traditional(text).collect::<Vec<_>>();
+        for entry in self.lookup_simplified(text) {
+            if !entries.iter().any(|e| std::ptr::eq(*e, entry)) {
+                entries.push(entry);
+            }
+        }
+        entries
+    }
+
+    /// Returns the pinyin of the likeliest reading of the traditional characters,
+    /// falling back to the simplified characters.
+    pub fn pinyin<T: ToString>(&self, text: &T) -> Option<String> {
+        self.pinyin_for(text, Script::Traditional)
+    }
+
+    /// Returns the pinyin of the likeliest reading of the characters, see
+    /// [`Dictionary::readings`].
+    pub fn pinyin_for<T: ToString>(&self, text: &T, script: Script) -> Option<String> {
+        let text = text.to_string();
+        self.readings(&text, script)
+            .first()
+            .map(|entry| entry.pinyin.to_string())
+    }
+
+    /// Returns every reading of the characters with its gloss, the likeliest
+    /// first. Matches in the chosen script come first, traditional unless
+    /// [`Script::Simplified`], then the most frequent reading of a polyphonic
+    /// character, and proper nouns, surnames and variant forms last.
+    pub fn readings<'a>(&'a self, text: &'a str, script: Script) -> Vec<&'a DictObject> {
+        let mut readings = self.lookup(text);
+        let other_script = |entry: &DictObject| match script {
+            Script::Simplified => &*entry.simplified != text,
+            Script::Traditional | Script::Both => &*entry.traditional != text,
+        };
+        let common = COMMON_READINGS
+            .iter()
+            .find(|(c, _)| text.chars().eq([*c]))
+            .map(|(_, pinyin)| *pinyin);
+        let rarer = |entry: &DictObject| {
+            common.is_some_and(|common| pinyin::to_numbered(&entry.pinyin) != common)
+        };
+        readings.sort_by_key(|entry| (other_script(entry), rarer(entry), entry.is_uncommon()));
+        readings
+    }
+
+    /// Splits a word or sentence into dictionary words, single characters for
+    /// the rest, and what is not Chinese, see [`Dictionary::segment`].
+    pub fn tokenize(&self, text: &str, script: Script) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let chars = text.chars().collect::<Vec<_>>();
+        let mut start = 0;
+        while start < chars.len() {
+            let is_chinese = is_hanzi(chars[start]);
+            let len = chars[start..]
+                .iter()
+                .take_while(|c| is_hanzi(**c) == is_chinese)
+                .count();
+            let run = &chars[start..start + len];
+            if is_chinese {
+                tokens.extend(self.segment(run, script));
+            } else {
+                let other = run.iter().collect::<String>();
+                if !other.trim().is_empty() {
+                    tokens.push(Token::Other(other.trim().to_string()));
+                }
+            }
+            start += len;
+        }
+        tokens
+    }
+
+    /// Splits a run of hanzi into the fewest dictionary words and single
+    /// characters. Of splits with as few words the one with the longest words
+    /// at the end is taken, e.g. 我/不/明白 over 我/不明/白.
+    fn segment(&self, chars: &[char], script: Script) -> Vec<Token> {
+        // The fewest words the first characters split into, with the length
+        // and the pinyin of the last word
+        let mut best: Vec<(usize, usize, Option<String>)> = vec![(0, 0, None)];
+        for end in 1..=chars.len() {
+            let mut best_end: Option<(usize, usize, Option<String>)> = None;
+            for len in (1..=MAX_WORD_LENGTH.min(end)).rev() {
+                let word = chars[end - len..end].iter().collect::<String>();
+                let pinyin = self.pinyin_for(&word, script);
+                if len > 1 && pinyin.is_none() {
+                    continue;
+                }
+                let count = best[end - len].0 + 1;
+                if best_end.as_ref().is_none_or(|(fewest, ..)| count < *fewest) {
+                    best_end = Some((count, len, pinyin));
+                }
+            }
+            best.push(best_end.unwrap_or_default());
+        }
+
+        let mut tokens = Vec::new();
+        let mut end = chars.len();
+        while end > 0 {
+            let (_, len, pinyin) = std::mem::take(&mut best[end]);
+            let text = chars[end - len..end].iter().collect();
+            tokens.push(Token::Word { text, pinyin });
+            end -= len;
+        }
+        tokens.reverse();
+        tokens
+    }
+
+    /// Returns the pinyin syllables of a word or sentence, see [`Dictionary::tokenize`].
+    /// Characters without an entry and punctuation are left out.
+    pub fn segment_pinyin(&self, text: &str, script: Script) -> String {
+        self.tokenize(text, script)
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Word { pinyin, .. } => pinyin,
+                Token::Other(_) => None,
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Finds entries by Chinese characters, by pinyin with or without tones,
+    /// e.g. `tian qi` or `tian1qi4`, or by words of the English translation.
+    /// Exact matches come first, then the entries containing the characters
+    /// or words, the likeliest and shortest first.
+    pub fn search<'a>(&'a self, query: &'a str, script: Script) -> Vec<&'a DictObject> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let rank = |entry: &&DictObject| (entry.is_uncommon(), entry.traditional.chars().count());
+        let (mut exact, mut partial): (Vec<&DictObject>, Vec<&DictObject>) =
+            if query.chars().any(is_hanzi) {
+                let exact = self.readings(query, script);
+                let partial = self
+                    .entries
+                    .iter()
+                    .filter(|e| e.traditional.contains(query) || e.simplified.contains(query))
+                    .collect();
+                (exact, partial)
+            } else {
+                let syllables = search_syllables(query);
+                let words = english_words(query);
+                let mut exact = Vec::new();
+                let mut partial = Vec::new();
+                for entry in &self.entries {
+                    let glosses = entry
+                        .english
+                        .split([';', ','])
+                        .map(english_words)
+                        .collect::<Vec<_>>();
+                    if syllables
+                        .as_ref()
+                        .is_some_and(|syllables| pinyin_matches(&entry.pinyin, syllables))
+                        || glosses.contains(&words)
+                    {
+                        exact.push(entry);
+                    } else if !words.is_empty()
+                        && glosses
+                            .iter()
+                            .any(|gloss| gloss.windows(words.len()).any(|w| w == words))
+                    {
+                        partial.push(entry);
+                    }
+                }
+                (exact, partial)
+            };
+        if !query.chars().any(is_hanzi) {
+            exact.sort_by_key(rank);
+        }
+        partial.sort_by_key(rank);
+        for entry in partial {
+            if !exact.iter().any(|e| std::ptr::eq(*e, entry)) {
+                exact.push(entry);
+            }
+        }
+        exact
+    }
+
+    pub fn write_index(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        writer.write_all(INDEX_MAGIC)?;
+        writer.write_all(&INDEX_VERSION.to_le_bytes())?;
+        writer.write_all(&u32::try_from(self.entries.len())?.to_le_bytes())?;
+        for entry in &self.entries {
+            for field in [
+                &entry.traditional,
+                &entry.simplified,
+                &entry.pinyin,
+                &entry.english,
+            ] {
+                writer.write_all(&u32::try_from(field.len())?.to_le_bytes())?;
+                writer.write_all(field.as_bytes())?;
+            }
+        }
+        for i in self.by_traditional.iter().chain(self.by_simplified.iter()) {
+            writer.write_all(&i.to_le_bytes())?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    fn read_index(path: &Path) -> Result<Self, Error> {
+        IndexReader::new(&fs::read(path)?).read()
+    }
+}
+
+struct IndexReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> IndexReader<'a> {
+    const fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self.position + len;
+        let slice = self
+            .bytes
+            .get(self.position..end)
+            .ok_or_else(|| anyhow!("Invalid data: premature EOF"))?;
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn str(&mut self) -> Result<Box<str>, Error> {
+        let len = self.u32()? as usize;
+        Ok(std::str::from_utf8(self.take(len)?)?.into())
+    }
+
+    fn index(&mut self, count: usize) -> Result<Box<[u32]>, Error> {
+        (0..count)
+            .map(|_| {
+                let i = self.u32()?;
+                if i as usize >= count {
+                    return Err(anyhow!("Invalid data: index out of bounds"));
+                }
+                Ok(i)
+            })
+            .collect()
+    }
+
+    fn read(mut self) -> Result<Dictionary, Error> {
+        if self.take(4)? != INDEX_MAGIC {
+            return Err(anyhow!("Invalid data: not a dictionary index"));
+        }
+        let version = self.u32()?;
+        if version != INDEX_VERSION {
+            return Err(anyhow!("Unsupported dictionary index version {}", version));
+        }
+        let count = self.u32()? as usize;
+        let entries = (0..count)
+            .map(|_| {
+                Ok(DictObject {
+                    traditional: self.str()?,
+                    simplified: self.str()?,
+                    pinyin: self.str()?,
+                    english: self.str()?,
+                })
+            })
+            .collect::<Result<Box<[_]>, Error>>()?;
+        let by_traditional = self.index(count)?;
+        let by_simplified = self.index(count)?;
+        Ok(Dictionary {
+            entries,
+            by_traditional,
+            by_simplified,
+        })
+    }
+}
+
+/// The syllables of a pinyin search with their tones, `None` where any tone
+/// matches. Returns `None` if the search is not pinyin.
+fn search_syllables(query: &str) -> Option<Vec<(String, Option<u8>)>> {
+    pinyin::segment(query)
+        .filter(|syllables| !syllables.is_empty())?
+        .iter()
+        .map(|syllable| {
+            let (letters, tone) = pinyin::split_tone(syllable)?;
+            let has_tone = tone != 5 || syllable.ends_with('5');
+            Some((letters, has_tone.then_some(tone)))
+        })
+        .collect()
+}
+
+fn pinyin_matches(pinyin: &str, syllables: &[(String, Option<u8>)]) -> bool {
+    let entry = pinyin.split_whitespace().collect::<Vec<_>>();
+    entry.len() == syllables.len()
+        && entry
+            .iter()
+            .zip(syllables)
+            .all(|(syllable, (letters, tone))| {
+                pinyin::split_tone(syllable).is_some_and(|(entry_letters, entry_tone)| {
+                    entry_letters == *letters && tone.is_none_or(|tone| tone == entry_tone)
+                })
+            })
+}
+
+/// The lowercase words of an English text without a leading "to", e.g. `pay back`.
+fn english_words(text: &str) -> Vec<String> {
+    let mut words = text
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    if words.len() > 1 && words[0] == "to" {
+        words.remove(0);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    fn entry(traditional: &str, simplified: &str, pinyin: &str, english: &str) -> DictObject {
+        DictObject {
+            traditional: traditional.into(),
+            simplified: simplified.into(),
+            english: english.into(),
+            pinyin: pinyin.into(),
+        }
+    }
+
+    fn get_dictionary() -> Dictionary {
+        Dictionary::new(Box::new([
+            entry("綠", "绿", "lǜ", "green"),
+            entry("了", "了", "le", "(completed action marker)"),
+            entry("你好", "你好", "nǐ hǎo", "hello"),
+            entry("了", "了", "liǎo", "to finish"),
+            entry("天氣", "天气", "tiān qì", "weather"),
+            entry("乾", "干", "gān", "dry"),
+            entry("幹", "干", "gàn", "to do"),
+            entry("干", "干", "gān", "to concern"),
+        ]))
+    }
+
+    #[test]
+    fn test_lookup() {
+        let dictionary = get_dictionary();
+        assert_eq!(dictionary.pinyin(&"你好"), Some("nǐ hǎo".to_string()));
+        assert_eq!(dictionary.pinyin(&'了'), Some("le".to_string()));
+        assert_eq!(dictionary.pinyin(&"你"), None);
+        assert_eq!(dictionary.pinyin(&"天气"), Some("tiān qì".to_string()));
+        assert_eq!(
+            dictionary
+                .lookup_traditional("了")
+                .map(|e| e.pinyin.as_ref())
+                .collect::<Vec<_>>(),
+            vec!["le", "liǎo"]
+        );
+        assert_eq!(
+            dictionary
+                .lookup_simplified("天气")
+                .map(|e| e.traditional.as_ref())
+                .collect::<Vec<_>>(),
+            vec!["天氣"]
+        );
+        assert_eq!(dictionary.lookup("了").len(), 2);
+        assert_eq!(dictionary.pinyin(&"干"), Some("gān".to_string()));
+        assert_eq!(
+            dictionary.pinyin_for(&"幹", Script::Simplified),
+            Some("gàn".to_string())
+        );
+        assert_eq!(
+            dictionary
+                .lookup_simplified("干")
+                .map(|e| e.pinyin.as_ref())
+                .collect::<Vec<_>>(),
+            vec!["gān", "gàn", "gān"]
+        );
+        assert_eq!(dictionary.lookup("绿")[0].pinyin.as_ref(), "lǜ");
+    }
+
+    #[test]
+    fn test_readings() {
+        let dictionary = Dictionary::new(Box::new([
+            entry("人", "人", "rén", "person"),
+            entry("得", "得", "dé", "to obtain"),
+            entry("得", "得", "de", "structural particle"),
+            entry("還", "还", "Huán", "surname Huan"),
+            entry("還", "还", "hái", "still"),
+            entry("還", "还", "huán", "to pay back"),
+            entry("行", "行", "háng", "row"),
+            entry("行", "行", "xíng", "to walk"),
+            entry("行人", "行人", "xíng rén", "pedestrian"),
+            entry("銀行", "银行", "yín háng", "bank"),
+            entry("銀", "银", "yín", "silver"),
+            entry("好", "好", "hǎo", "good"),
+            entry("我", "我", "wǒ", "I"),
+            entry("不", "不", "bù", "not"),
+            entry("不明", "不明", "bù míng", "unknown"),
+            entry("明白", "明白", "míng bai", "to understand"),
+            entry("白", "白", "bái", "white"),
+        ]));
+        assert_eq!(
+            dictionary
+                .readings("还", Script::Simplified)
+                .iter()
+                .map(|e| e.pinyin.as_ref())
+                .collect::<Vec<_>>(),
+            vec!["hái", "huán", "Huán"]
+        );
+        assert_eq!(dictionary.pinyin(&"還"), Some("hái".to_string()));
+        assert_eq!(dictionary.pinyin(&"行"), Some("xíng".to_string()));
+        assert_eq!(dictionary.pinyin(&"得"), Some("de".to_string()));
+        assert_eq!(
+            dictionary.segment_pinyin("銀行", Script::Traditional),
+            "yín háng"
+        );
+        assert_eq!(
+            dictionary.segment_pinyin("行人還得好。銀行", Script::Traditional),
+            "xíng rén hái de hǎo yín háng"
+        );
+        assert_eq!(dictionary.segment_pinyin("ok", Script::Traditional), "");
+        // Not 我/不明/白
+        assert_eq!(
+            dictionary.segment_pinyin("我不明白", Script::Traditional),
+            "wǒ bù míng bai"
+        );
+
+        let word = |text: &str, pinyin: Option<&str>| Token::Word {
+            text: text.to_string(),
+            pinyin: pinyin.map(ToString::to_string),
+        };
+        assert_eq!(
+            dictionary.tokenize("銀行好嘛？ ok", Script::Traditional),
+            vec![
+                word("銀行", Some("yín háng")),
+                word("好", Some("hǎo")),
+                word("嘛", None),
+                Token::Other("？ ok".to_string()),
+            ]
+        );
+        assert_eq!(
+            dictionary.tokenize("我不明白", Script::Traditional),
+            vec![
+                word("我", Some("wǒ")),
+                word("不", Some("bù")),
+                word("明白", Some("míng bai")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let dictionary = get_dictionary();
+        let search = |query: &str| {
+            dictionary
+                .search(query, Script::Traditional)
+                .iter()
+                .map(|e| format!("{} {}", e.traditional, e.pinyin))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(search("了"), vec!["了 le", "了 liǎo"]);
+        assert_eq!(search("天"), vec!["天氣 tiān qì"]);
+        assert_eq!(search("tianqi"), vec!["天氣 tiān qì"]);
+        assert_eq!(search("tian1 qi4"), vec!["天氣 tiān qì"]);
+        assert_eq!(search("tian1 qi3"), Vec::<String>::new());
+        assert_eq!(search("gan"), vec!["乾 gān", "幹 gàn", "干 gān"]);
+        assert_eq!(search("gān"), vec!["乾 gān", "干 gān"]);
+        assert_eq!(search("lv4"), vec!["綠 lǜ"]);
+        assert_eq!(search("Weather"), vec!["天氣 tiān qì"]);
+        assert_eq!(search("do"), vec!["幹 gàn"]);
+        assert_eq!(search("finish"), vec!["了 liǎo"]);
+        assert_eq!(search("action"), vec!["了 le"]);
+        assert!(search(" ").is_empty());
+    }
+
+    #[test]
+    fn test_index_round_trip() {
+        let path = std::env::temp_dir().join(format!("lingua_test_{}.idx", std::process::id()));
+        get_dictionary().write_index(&path).unwrap();
+        let dictionary = Dictionary::read_index(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(dictionary.entries.len(), 8);
+        assert_eq!(dictionary.pinyin(&"天氣"), Some("tiān qì".to_string()));
+        assert_eq!(dictionary.lookup_simplified("绿").count(), 1);
+    }
+
+    #[test]
+    fn test_load_builds_index_in_cache() {
+        let dir = std::env::temp_dir().join(format!("lingua_test_{}_load", std::process::id()));
+        let (data_dir, cache_dir) = (dir.join("data"), dir.join("cache"));
+        fs::create_dir_all(&data_dir).unwrap();
+        let json = serde_json::to_vec(&get_dictionary().entries).unwrap();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&json).unwrap();
+        fs::write(data_dir.join(DICTIONARY_FILE), encoder.finish().unwrap()).unwrap();
+
+        let dictionary = Dictionary::load(&data_dir, &cache_dir).unwrap();
+        assert_eq!(dictionary.entries.len(), 8);
+        assert!(!data_dir.join(DICTIONARY_INDEX_FILE).exists());
+        assert!(is_up_to_date(
+            &cache_dir.join(DICTIONARY_INDEX_FILE),
+            &data_dir.join(DICTIONARY_FILE)
+        ));
+        let dictionary = Dictionary::load(&data_dir, &cache_dir).unwrap();
+        assert_eq!(dictionary.pinyin(&"天氣"), Some("tiān qì".to_string()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
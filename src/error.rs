@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Why a pronunciation could not be loaded or played.
+#[derive(Error, Debug)]
+pub enum AudioError {
+    #[error("No recording of {0} in the tone archive")]
+    MissingClip(String),
+    #[error("Tone archive {} not found", .0.display())]
+    MissingArchive(PathBuf),
+    #[error("Could not read the tone archive: {0}")]
+    Archive(std::io::Error),
+    #[error("Could not download the pronunciation: {0}")]
+    Download(#[from] reqwest::Error),
+    #[error("Text-to-speech engine failed: {0}")]
+    Engine(String),
+    #[error("No audio output device: {0}")]
+    NoDevice(#[from] rodio::StreamError),
+    #[error("Could not start playback: {0}")]
+    Playback(#[from] rodio::PlayError),
+    #[error("Could not decode the audio: {0}")]
+    Decode(#[from] rodio::decoder::DecoderError),
+}
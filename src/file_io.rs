@@ -1,107 +1,201 @@
+use super::dictionary::DICTIONARY_FILE;
+use super::error::AudioError;
 use super::models::{BaseModel, BaseModelDto, DictObject, ToBaseModel};
 use anyhow::{anyhow, Error};
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
 use serde::{de::DeserializeOwned, Serialize};
-use serde_json::{from_str, to_string, Deserializer};
-use std::fs::File;
-use std::io::{self, BufReader, Read, Write};
+use serde_json::{from_str, to_string};
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use tar::{Archive, Builder};
 
-pub fn get_audio_file_from_compressed_archive(file_name: &str) -> Vec<u8> {
-    let file = File::open("files/tone_archive.tar.zlib").unwrap();
-    let decoder = ZlibDecoder::new(file);
+/// Directory given with `--data-dir`, set once at startup.
+static DATA_DIR_FLAG: OnceLock<PathBuf> = OnceLock::new();
+static DATA_DIR: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+
+/// Scans the compressed archive for a clip, used when there is no index.
+pub fn get_audio_file_from_compressed_archive(
+    archive_path: &Path,
+    file_name: &str,
+) -> Result<Vec<u8>, AudioError> {
+    if !archive_path.exists() {
+        return Err(AudioError::MissingArchive(archive_path.to_path_buf()));
+    }
+    find_in_compressed_archive(archive_path, file_name)
+        .map_err(AudioError::Archive)?
+        .ok_or_else(|| AudioError::MissingClip(file_name.to_string()))
+}
+
+fn find_in_compressed_archive(
+    archive_path: &Path,
+    file_name: &str,
+) -> Result<Option<Vec<u8>>, std::io::Error> {
+    let decoder = ZlibDecoder::new(File::open(archive_path)?);
     let mut archive = Archive::new(decoder);
 
-    for file in archive.entries().unwrap() {
-        let mut file = file.unwrap();
-        if file.path().unwrap().to_str().unwrap() == file_name {
+    for file in archive.entries()? {
+        let mut file = file?;
+        if file.path()?.to_str() == Some(file_name) {
             let mut buffer = Vec::new();
-            file.read_to_end(&mut buffer).unwrap();
-            return buffer;
+            file.read_to_end(&mut buffer)?;
+            return Ok(Some(buffer));
         }
     }
-    Vec::new()
-}
-
-pub fn get_pinyin_from_compressed_json<T: ToString>(text: &T) -> Option<String> {
-    fn iter_json_array<T: DeserializeOwned, R: Read>(
-        mut reader: R,
-    ) -> impl Iterator<Item = Result<T, Error>> {
-        fn read_skipping_ws(mut reader: impl Read) -> io::Result<u8> {
-            loop {
-                let mut byte = 0u8;
-                reader.read_exact(std::slice::from_mut(&mut byte))?;
-                if !byte.is_ascii_whitespace() {
-                    return Ok(byte);
-                }
-            }
-        }
+    Ok(None)
+}
 
-        fn invalid_data(msg: &str) -> Error {
-            anyhow!("Invalid data: {}", msg)
-        }
+/// Whether a file built from `source_path`, such as an index, exists and is
+/// not older than it. Kept if the source is missing.
+pub fn is_up_to_date(path: &Path, source_path: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(path), modified(source_path)) {
+        (Some(built), Some(source)) => built >= source,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
 
-        fn deserialize_single<T: DeserializeOwned, R: Read>(reader: R) -> Result<T, Error> {
-            let next_obj = Deserializer::from_reader(reader).into_iter::<T>().next();
-            next_obj.map_or_else(
-                || Err(invalid_data("premature EOF")),
-                |result| result.map_err(Into::into),
-            )
-        }
+/// Directory for learner state such as the review schedule.
+///
+/// Uses `LINGUA_STATE_DIR`, then `$XDG_STATE_HOME/lingua_cli` and finally
+/// `~/.local/state/lingua_cli`.
+pub fn state_dir() -> PathBuf {
+    env::var_os("LINGUA_STATE_DIR")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("XDG_STATE_HOME").map(|dir| PathBuf::from(dir).join("lingua_cli")))
+        .or_else(|| {
+            env::var_os("HOME").map(|dir| PathBuf::from(dir).join(".local/state/lingua_cli"))
+        })
+        .unwrap_or_else(|| PathBuf::from("files"))
+}
 
-        fn yield_next_obj<T: DeserializeOwned, R: Read>(
-            mut reader: R,
-            at_start: &mut bool,
-        ) -> Result<Option<T>, Error> {
-            if *at_start {
-                match read_skipping_ws(&mut reader)? {
-                    b',' => deserialize_single(reader).map(Some),
-                    b']' => Ok(None),
-                    _ => Err(invalid_data("`,` or `]` not found")),
-                }
-            } else {
-                *at_start = true;
-                if read_skipping_ws(&mut reader)? == b'[' {
-                    // read the next char to see if the array is empty
-                    let peek = read_skipping_ws(&mut reader)?;
-                    if peek == b']' {
-                        Ok(None)
-                    } else {
-                        deserialize_single(io::Cursor::new([peek]).chain(reader)).map(Some)
-                    }
-                } else {
-                    Err(invalid_data("`[` not found"))
-                }
-            }
-        }
+/// Directory for files that can be recreated, such as downloaded audio.
+///
+/// Uses `LINGUA_CACHE_DIR`, then `$XDG_CACHE_HOME/lingua_cli` and finally
+/// `~/.cache/lingua_cli`.
+pub fn cache_dir() -> PathBuf {
+    env::var_os("LINGUA_CACHE_DIR")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("XDG_CACHE_HOME").map(|dir| PathBuf::from(dir).join("lingua_cli")))
+        .or_else(|| env::var_os("HOME").map(|dir| PathBuf::from(dir).join(".cache/lingua_cli")))
+        .unwrap_or_else(|| PathBuf::from("files/cache"))
+}
 
-        let mut at_start = false;
-        std::iter::from_fn(move || yield_next_obj(&mut reader, &mut at_start).transpose())
+/// Directory of the config file.
+///
+/// Uses `LINGUA_CONFIG_DIR`, then `$XDG_CONFIG_HOME/lingua_cli` and finally
+/// `~/.config/lingua_cli`.
+pub fn config_dir() -> PathBuf {
+    env::var_os("LINGUA_CONFIG_DIR")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("XDG_CONFIG_HOME").map(|dir| PathBuf::from(dir).join("lingua_cli")))
+        .or_else(|| env::var_os("HOME").map(|dir| PathBuf::from(dir).join(".config/lingua_cli")))
+        .unwrap_or_else(|| PathBuf::from("files"))
+}
+
+/// Uses `dir` as the data directory, must be called before [`data_dir`].
+pub fn set_data_dir_flag(dir: PathBuf) {
+    if DATA_DIR_FLAG.set(dir).is_err() {
+        log::warn!("The data directory is already set");
     }
+}
 
-    let file = File::open("files/dictionary.json.zlib").unwrap();
-    let decoder = ZlibDecoder::new(file);
-    let reader = BufReader::new(decoder);
-
-    for value in iter_json_array::<DictObject, _>(reader) {
-        match value {
-            Ok(v) => {
-                if v.traditional != text.to_string().into() {
-                    continue;
-                }
-                return Some(v.pinyin.into());
-            }
-            Err(e) => {
-                log::error!("Error: {}", e);
-            }
-        }
+/// Directory of the decks, the dictionary and the tone archive.
+///
+/// `--data-dir` or else `LINGUA_DATA_DIR` if set, which must contain the
+/// dictionary. Otherwise the first of `$XDG_DATA_HOME/lingua_cli`
+/// (`~/.local/share/lingua_cli`), `files` next to the executable and `files`
+/// in the working directory that contains the dictionary.
+pub fn data_dir() -> Result<PathBuf, Error> {
+    DATA_DIR
+        .get_or_init(|| {
+            explicit_data_dir()
+                .map_or_else(
+                    || find_data_dir(&data_dir_candidates()),
+                    |(setting, dir)| check_data_dir(setting, dir),
+                )
+                .map_err(|e| e.to_string())
+        })
+        .clone()
+        .map_err(|e| anyhow!(e))
+}
+
+/// The data directory set by the learner and the setting it was set with.
+fn explicit_data_dir() -> Option<(&'static str, PathBuf)> {
+    DATA_DIR_FLAG
+        .get()
+        .map(|dir| ("--data-dir", dir.clone()))
+        .or_else(|| {
+            env::var_os("LINGUA_DATA_DIR").map(|dir| ("LINGUA_DATA_DIR", PathBuf::from(dir)))
+        })
+}
+
+fn check_data_dir(setting: &str, dir: PathBuf) -> Result<PathBuf, Error> {
+    if dir.join(DICTIONARY_FILE).is_file() {
+        Ok(dir)
+    } else {
+        Err(anyhow!(
+            "The data directory {} set with {} does not contain {}",
+            dir.display(),
+            setting,
+            DICTIONARY_FILE
+        ))
     }
-    None
 }
 
-pub fn load_from_file_mut<T>(file_path: &str) -> Result<Vec<T>, Error>
+fn data_dir_candidates() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    candidates.extend(
+        env::var_os("XDG_DATA_HOME")
+            .map(|dir| PathBuf::from(dir).join("lingua_cli"))
+            .or_else(|| {
+                env::var_os("HOME").map(|dir| PathBuf::from(dir).join(".local/share/lingua_cli"))
+            }),
+    );
+    candidates.extend(
+        env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join("files"))),
+    );
+    candidates.push(PathBuf::from("files"));
+    candidates
+}
+
+fn find_data_dir(candidates: &[PathBuf]) -> Result<PathBuf, Error> {
+    candidates
+        .iter()
+        .find(|dir| dir.join(DICTIONARY_FILE).is_file())
+        .cloned()
+        .ok_or_else(|| {
+            let searched = candidates
+                .iter()
+                .map(|dir| dir.display().to_string())
+                .collect::<Vec<_>>()
+                .join("\n  ");
+            anyhow!(
+                "Could not find the data files ({} and the decks). Searched:\n  {}\n\
+                Set the data directory with --data-dir or LINGUA_DATA_DIR.",
+                DICTIONARY_FILE,
+                searched
+            )
+        })
+}
+
+pub fn read_compressed_dict(file_path: &Path) -> Result<Box<[DictObject]>, Error> {
+    let file = File::open(file_path)?;
+    let mut decoder = ZlibDecoder::new(file);
+    let mut buffer = Vec::new();
+    decoder.read_to_end(&mut buffer)?;
+    let models: Vec<DictObject> = serde_json::from_slice(&buffer)?;
+    Ok(models.into_boxed_slice())
+}
+
+pub fn load_from_file_mut<T>(file_path: impl AsRef<Path>) -> Result<Vec<T>, Error>
 where
     T: DeserializeOwned,
 {
@@ -112,14 +206,14 @@ where
     Ok(models)
 }
 
-pub fn load_from_file<T>(file_path: &str) -> Result<Box<[T]>, Error>
+pub fn load_from_file<T>(file_path: impl AsRef<Path>) -> Result<Box<[T]>, Error>
 where
     T: DeserializeOwned,
 {
     Ok(load_from_file_mut(file_path)?.into_boxed_slice())
 }
 
-pub fn read_json(path: &str) -> Result<Box<[BaseModel]>, Error> {
+pub fn read_json(path: &Path) -> Result<Box<[BaseModel]>, Error> {
     let values = load_from_file::<BaseModelDto>(path)?;
     Ok(values
         .iter()
@@ -129,9 +223,10 @@ pub fn read_json(path: &str) -> Result<Box<[BaseModel]>, Error> {
 }
 #[cfg(not(feature = "ignore_tools_arcive"))]
 pub mod tools_archive {
+    use super::super::dictionary::DICTIONARY_FILE;
     use super::{
-        from_str, load_from_file, to_string, Archive, Builder, Compression, DictObject, Error,
-        File, Read, Serialize, Write, ZlibEncoder,
+        data_dir, load_from_file, read_compressed_dict, to_string, Archive, Builder, Compression,
+        DictObject, Error, File, Read, Serialize, Write, ZlibEncoder,
     };
 
     pub fn _save_to_file<U, T>(models: T, file_path: &str) -> Result<(), Error>
@@ -151,13 +246,7 @@ pub mod tools_archive {
     }
 
     pub fn _read_compressed_dict() -> Result<Box<[DictObject]>, Error> {
-        let file_path = "files/dictionary.json.zlib";
-        let file = File::open(file_path)?;
-        let mut decoder = flate2::read::ZlibDecoder::new(file);
-        let mut buffer = Vec::new();
-        decoder.read_to_end(&mut buffer)?;
-        let models: Vec<DictObject> = from_str(&String::from_utf8(buffer)?)?;
-        Ok(models.into_boxed_slice())
+        read_compressed_dict(&data_dir()?.join(DICTIONARY_FILE))
     }
 
     pub fn _compress_file(path: &str) {
@@ -241,3 +330,36 @@ pub mod tools_archive {
         Vec::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_data_dir() {
+        let root = env::temp_dir().join(format!("lingua_test_{}_data", std::process::id()));
+        let (empty, data) = (root.join("empty"), root.join("data"));
+        std::fs::create_dir_all(&empty).unwrap();
+        std::fs::create_dir_all(&data).unwrap();
+        std::fs::write(data.join(DICTIONARY_FILE), []).unwrap();
+
+        let missing = root.join("missing");
+        assert_eq!(
+            find_data_dir(&[missing.clone(), empty.clone(), data.clone()]).unwrap(),
+            data
+        );
+        let error = find_data_dir(&[missing.clone(), empty.clone()])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains(&missing.display().to_string()));
+        assert!(error.contains(&empty.display().to_string()));
+
+        assert_eq!(check_data_dir("--data-dir", data.clone()).unwrap(), data);
+        let error = check_data_dir("LINGUA_DATA_DIR", empty.clone())
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains(&empty.display().to_string()));
+        assert!(error.contains("LINGUA_DATA_DIR"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,104 +1,602 @@
-use super::models::{BaseModel, Pronouncation, Voice};
-use super::utils::{get_random_base_model, StyledWrite};
-use super::PHRASES;
-use anyhow::Error;
-use console::Term;
+use super::models::{BaseModel, Pronouncation, Script, Syllable, Voice};
+use super::scheduler::{self, Scheduler};
+use super::stats::{Direction, History, RoundRecord};
+use super::tts::Speaker;
+use super::utils::{get_random_base_model, get_scheduled_base_model, StyledWrite};
+use super::TONE_ARCHIVE;
+use anyhow::{anyhow, Error};
+use console::{style, Term};
 use crossbeam::channel::{bounded, Receiver};
-use rand::random;
-use std::collections::VecDeque;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use std::{cmp, thread};
 
+/// How long the loader waits before trying again when it has no new round to send.
+const LOADER_RETRY_DELAY: Duration = Duration::from_millis(100);
+
 pub enum Mode {
     Words,
     Phrases,
     Sentences,
     Tones,
-    Random,
+    Random(RandomWeights),
 }
 
 impl Mode {
     pub fn get_json_file(&self) -> &'static [BaseModel] {
         match self {
-            Mode::Words => &super::WORDS,
-            Mode::Phrases => &super::PHRASES,
-            Mode::Sentences => &super::SENTENCES,
-            Mode::Tones => &super::PHRASES,
-            Mode::Random => &super::PHRASES,
+            Self::Words | Self::Tones => &super::WORDS,
+            Self::Phrases | Self::Random(_) => &super::PHRASES,
+            Self::Sentences => &super::SENTENCES,
+        }
+    }
+
+    /// Name used for the mode in the statistics.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Words => "words",
+            Self::Phrases => "phrases",
+            Self::Sentences => "sentences",
+            Self::Tones => "tones",
+            Self::Random(_) => "random",
+        }
+    }
+
+    /// The mode without its tone rounds if they cannot be heard, `None` if
+    /// that leaves nothing to ask.
+    fn audible(self, tones_audible: bool) -> Option<Self> {
+        match self {
+            _ if tones_audible => Some(self),
+            Self::Tones => None,
+            Self::Random(weights) => weights.without_tones().map(Self::Random),
+            mode => Some(mode),
+        }
+    }
+
+    const fn selected_text(&self) -> &'static str {
+        match self {
+            Self::Words => "Words selected!\n",
+            Self::Phrases => "Phrases selected!\n",
+            Self::Sentences => "Sentences selected!\n",
+            Self::Tones => "Tones selected!\n",
+            Self::Random(_) => "Random selected!\n",
+        }
+    }
+}
+
+/// Relative weights used by [`Mode::Random`] to pick the source of each round.
+///
+/// Can be overridden with e.g. `LINGUA_RANDOM_WEIGHTS=words=4,phrases=3,sentences=2,tones=1`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RandomWeights {
+    words: u32,
+    phrases: u32,
+    sentences: u32,
+    tones: u32,
+}
+
+impl Default for RandomWeights {
+    fn default() -> Self {
+        Self {
+            words: 4,
+            phrases: 3,
+            sentences: 2,
+            tones: 1,
+        }
+    }
+}
+
+impl RandomWeights {
+    /// Parses weights in the form `words=4,phrases=3,sentences=2,tones=1`,
+    /// leaving out modes gives them a weight of zero.
+    pub fn parse(value: &str) -> Result<Self, Error> {
+        let mut weights = Self {
+            words: 0,
+            phrases: 0,
+            sentences: 0,
+            tones: 0,
+        };
+        for pair in value.split(',').filter(|p| !p.trim().is_empty()) {
+            let (key, weight) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expected `mode=weight`, got `{}`", pair.trim()))?;
+            let weight = weight.trim().parse::<u32>()?;
+            match key.trim().to_lowercase().as_str() {
+                "words" => weights.words = weight,
+                "phrases" => weights.phrases = weight,
+                "sentences" => weights.sentences = weight,
+                "tones" => weights.tones = weight,
+                other => return Err(anyhow!("unknown mode `{}`", other)),
+            }
+        }
+        weights.validate()?;
+        Ok(weights)
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.as_array().iter().all(|w| *w == 0) {
+            return Err(anyhow!("at least one weight must be greater than zero"));
+        }
+        Ok(())
+    }
+
+    /// The weights with tone rounds left out, `None` if no other rounds are left.
+    fn without_tones(&self) -> Option<Self> {
+        let weights = Self {
+            tones: 0,
+            ..self.clone()
+        };
+        weights.validate().ok().map(|()| weights)
+    }
+
+    const fn as_array(&self) -> [u32; 4] {
+        [self.words, self.phrases, self.sentences, self.tones]
+    }
+
+    /// Picks the mode of the next round according to the weights.
+    fn pick(&self) -> Mode {
+        let index = WeightedIndex::new(self.as_array())
+            .map_or(0, |dist| dist.sample(&mut rand::thread_rng()));
+        match index {
+            0 => Mode::Words,
+            1 => Mode::Phrases,
+            2 => Mode::Sentences,
+            _ => Mode::Tones,
+        }
+    }
+}
+
+/// How answers are checked, see [`Config`](super::config::Config).
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct AnswerOptions {
+    /// Pinyin answers must have the correct tones
+    pub strict_tones: bool,
+    /// Only answers in Chinese characters are accepted
+    pub require_characters: bool,
+    /// Notes are only shown with `:n` instead of after every answer
+    pub notes_on_demand: bool,
+    /// Questions show the tones as spoken next to the dictionary tones
+    pub spoken_tones: bool,
+    /// Pronunciations are shown in zhuyin instead of pinyin
+    pub zhuyin: bool,
+    /// Characters shown and accepted
+    pub script: Script,
+}
+
+/// Settings of a single drill, from the config and the `play` subcommand.
+#[derive(Clone, Debug)]
+pub struct Session {
+    /// Only ask in this direction, both directions at random when `None`
+    pub direction: Option<Direction>,
+    /// Share of rounds asked from Chinese to English when both directions are asked
+    pub chinese_to_english_percent: u8,
+    /// Stop after this many rounds, play until interrupted when `None`
+    pub count: Option<usize>,
+    pub audio: bool,
+    /// Voices the pronunciation is picked from
+    pub voices: Vec<Voice>,
+    pub speaker: Arc<Speaker>,
+}
+
+/// A single question sent from the loader thread to the game loop.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Round {
+    Translation { mode: &'static str, word: BaseModel },
+    Tone(Syllable),
+}
+
+impl Round {
+    fn create(
+        mode: &Mode,
+        script: Script,
+        scheduler: &Mutex<Scheduler>,
+        excluded: &[String],
+    ) -> Option<Self> {
+        match mode {
+            Mode::Random(weights) => Self::create(&weights.pick(), script, scheduler, excluded),
+            Mode::Tones => get_random_base_model(mode, script)
+                .syllables()
+                .into_iter()
+                .filter(|syllable| (1..=4).contains(&syllable.tone))
+                .collect::<Vec<_>>()
+                .choose(&mut rand::thread_rng())
+                .cloned()
+                .map(Self::Tone),
+            _ => Some(Self::Translation {
+                mode: mode.name(),
+                word: get_scheduled_base_model(mode, scheduler, excluded, script),
+            }),
+        }
+    }
+
+    fn key(&self) -> Option<String> {
+        match self {
+            Self::Translation { word, .. } => Some(word.key()),
+            Self::Tone(_) => None,
+        }
+    }
+
+    fn pronounce(&mut self, voice: Voice, speaker: &Speaker) -> Pronouncation {
+        match self {
+            Self::Translation { word, .. } => Pronouncation::create_from(word, voice, speaker),
+            Self::Tone(syllable) => Pronouncation::create_from_syllable(syllable, voice),
+        }
+    }
+}
+
+/// Commands that can be typed instead of an answer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RoundCommand {
+    Replay,
+    Hint,
+    Skip,
+    Notes,
+    Quit,
+}
+
+impl RoundCommand {
+    fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            ":r" | ":replay" => Some(Self::Replay),
+            ":h" | ":hint" => Some(Self::Hint),
+            ":s" | ":skip" => Some(Self::Skip),
+            ":n" | ":notes" => Some(Self::Notes),
+            ":q" | ":quit" => Some(Self::Quit),
+            _ => None,
+        }
+    }
+
+    const fn help() -> &'static str {
+        "Type :r to replay the audio, :h for a hint, :n for notes, :s to skip or :q to quit.\n"
+    }
+}
+
+/// What the learner did in a round.
+enum Reply {
+    Answer(String),
+    Skip,
+    Quit,
+}
+
+/// Receives the rounds prepared by the loader thread and stops the thread when dropped.
+struct RoundLoader {
+    receiver: Receiver<(Round, Pronouncation)>,
+    stopped: Arc<AtomicBool>,
+    /// Keys of the rounds sent but not reviewed yet, which are not picked again
+    in_flight: Arc<Mutex<HashSet<String>>>,
+}
+
+impl RoundLoader {
+    /// Lets the word of the round be picked again once its review is recorded.
+    fn finish(&self, key: Option<String>) {
+        if let (Some(key), Ok(mut in_flight)) = (key, self.in_flight.lock()) {
+            in_flight.remove(&key);
         }
     }
 }
 
+impl Drop for RoundLoader {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
 pub struct Language {}
 
 impl Language {
-    fn start_basemodel_channel(mode: Mode) -> Receiver<(BaseModel, Pronouncation)> {
-        let channel_max_length = cmp::min(10, PHRASES.len());
+    fn start_basemodel_channel(
+        mode: Mode,
+        script: Script,
+        session: Session,
+        scheduler: Arc<Mutex<Scheduler>>,
+    ) -> RoundLoader {
+        let channel_max_length = cmp::min(10, mode.get_json_file().len());
         let (sender, receiver) = bounded(channel_max_length);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stop = stopped.clone();
+        let in_flight = Arc::new(Mutex::new(HashSet::new()));
+        let sent = in_flight.clone();
 
         thread::spawn(move || {
-            let mut base_models: VecDeque<BaseModel> = VecDeque::with_capacity(channel_max_length);
+            let mut rounds: VecDeque<Round> = VecDeque::with_capacity(channel_max_length);
             loop {
-                let mut base_model = get_random_base_model(&mode, true);
-
-                while sender.is_full() {
-                    thread::sleep(std::time::Duration::from_millis(1200));
+                while sender.is_full() && !stop.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(1200));
+                }
+                if stop.load(Ordering::Relaxed) {
+                    break;
                 }
 
-                if sender.len() < base_models.len() {
-                    let pop_count = base_models.len() - sender.len();
+                if sender.len() < rounds.len() {
+                    let pop_count = rounds.len() - sender.len();
                     for _ in 0..pop_count {
-                        base_models.pop_front();
+                        rounds.pop_front();
                     }
                 }
 
-                if !sender.is_full() && !base_models.contains(&base_model) {
-                    let voice = Voice::random();
-                    let tone: Pronouncation = Pronouncation::create_from(&mut base_model, &voice);
-                    sender.send((base_model.clone(), tone)).unwrap();
-                    base_models.push_back(base_model);
+                let excluded = sent.lock().map_or_else(
+                    |_| Vec::new(),
+                    |sent| sent.iter().cloned().collect::<Vec<_>>(),
+                );
+                let Some(mut round) = Round::create(&mode, script, &scheduler, &excluded) else {
+                    thread::sleep(LOADER_RETRY_DELAY);
+                    continue;
+                };
+
+                // The scheduler falls back to a random word when every word is
+                // excluded, so wait for a round to be answered
+                let repeated = round.key().is_some_and(|key| excluded.contains(&key));
+                if repeated || rounds.contains(&round) {
+                    thread::sleep(LOADER_RETRY_DELAY);
+                } else if !sender.is_full() {
+                    let tone = if session.audio {
+                        round.pronounce(Voice::random_from(&session.voices), &session.speaker)
+                    } else {
+                        Pronouncation::silent()
+                    };
+                    if let (Some(key), Ok(mut sent)) = (round.key(), sent.lock()) {
+                        sent.insert(key);
+                    }
+                    if sender.send((round.clone(), tone)).is_err() {
+                        // The drill is over
+                        break;
+                    }
+                    rounds.push_back(round);
                 }
             }
         });
-        receiver
+        RoundLoader {
+            receiver,
+            stopped,
+            in_flight,
+        }
     }
 
-    pub(super) fn run(terminal: &mut Term, mode: Mode) -> Result<(), Error> {
-        let receiver = Self::start_basemodel_channel(mode);
-        terminal.write_line("Words selected!\n")?;
-        let mut last_round_chinese: bool;
-
-        '_words: loop {
-            let (mut word, pronounce) = receiver.recv().unwrap();
-            let is_chinese = random::<bool>();
+    pub(super) fn run(
+        terminal: &mut Term,
+        mode: Mode,
+        options: AnswerOptions,
+        session: &Session,
+        history: &mut History,
+    ) -> Result<(), Error> {
+        let mut session = session.clone();
+        if session.audio {
+            let tones = matches!(mode, Mode::Tones);
+            if let Err(e) = Pronouncation::check_available(&session.speaker, tones) {
+                terminal
+                    .write_styled_line(style(format!("Continuing without audio: {e}")).yellow())?;
+                session.audio = false;
+            }
+        }
+        let tones_audible = session.audio && TONE_ARCHIVE.check().is_ok();
+        let Some(mode) = mode.audible(tones_audible) else {
+            terminal.write_styled_line(
+                style("Tones mode needs audio and the tone archive.\n").yellow(),
+            )?;
+            return Ok(());
+        };
+        terminal.write_line(mode.selected_text())?;
+        terminal.write_styled_line(style(RoundCommand::help()).dim())?;
+        let session_mode = mode.name();
+        let mut scheduler = Scheduler::load_default();
+        for deck in [Mode::Words, Mode::Phrases, Mode::Sentences] {
+            scheduler.migrate_keys(deck.get_json_file());
+        }
+        let scheduler = Arc::new(Mutex::new(scheduler));
+        let loader =
+            Self::start_basemodel_channel(mode, options.script, session.clone(), scheduler.clone());
 
-            let question = if is_chinese {
-                last_round_chinese = true;
-                word.question_chinese()
-            } else {
-                last_round_chinese = false;
-                word.question_english()
+        history.start_drill();
+        let mut played = 0;
+        while session.count.is_none_or(|count| played < count) {
+            let (round, pronounce) = loader.receiver.recv()?;
+            let key = round.key();
+            let record = match round {
+                Round::Translation { mode, mut word } => Self::translation_round(
+                    terminal, mode, &mut word, &pronounce, options, &session, &scheduler,
+                )?,
+                Round::Tone(syllable) => {
+                    Self::tone_round(terminal, &syllable, &pronounce, &session)?
+                }
+            };
+            let Some(record) = record else {
+                break;
             };
+            loader.finish(key);
+            history.record(record.in_session(session_mode));
+            played += 1;
+        }
+        terminal.write_styled_line(history.drill_summary())?;
+        Ok(())
+    }
 
-            terminal.write_question(&question)?;
-            if is_chinese {
-                if last_round_chinese {
-                    thread::sleep(std::time::Duration::from_millis(720));
-                }
-                pronounce.play_all().unwrap();
+    fn translation_round(
+        terminal: &mut Term,
+        mode: &'static str,
+        word: &mut BaseModel,
+        pronounce: &Pronouncation,
+        options: AnswerOptions,
+        session: &Session,
+        scheduler: &Mutex<Scheduler>,
+    ) -> Result<Option<RoundRecord>, Error> {
+        let is_chinese = match session.direction {
+            Some(Direction::ChineseToEnglish) => true,
+            Some(Direction::EnglishToChinese) => false,
+            _ => rand::thread_rng()
+                .gen_ratio(u32::from(session.chinese_to_english_percent.min(100)), 100),
+        };
+
+        let question = if is_chinese {
+            word.question_chinese(options)
+        } else {
+            word.question_english(options.require_characters)
+        };
+
+        terminal.write_question(&question)?;
+        if is_chinese && session.audio {
+            thread::sleep(std::time::Duration::from_millis(720));
+            Self::play(pronounce);
+        }
+
+        let started = Instant::now();
+        let hint = word.hint(is_chinese, options.zhuyin);
+        let notes = word.styled_notes();
+        let reply = Self::read_reply(terminal, pronounce, &hint, notes.as_deref())?;
+        let response_time = started.elapsed();
+        let answer = match reply {
+            Reply::Quit => return Ok(None),
+            Reply::Skip => word.skip(is_chinese, options),
+            Reply::Answer(input) if is_chinese => word.handle_chinese_response(&input, options),
+            Reply::Answer(input) => word.handle_english_response(&input, options),
+        };
+
+        terminal.write_line(&answer.feedback)?;
+        if let Ok(mut scheduler) = scheduler.lock() {
+            scheduler.review(word.key(), answer.verdict.into(), scheduler::now());
+            if let Err(e) = scheduler.save() {
+                log::warn!(
+                    "Could not save review state to {}: {}",
+                    scheduler.path().display(),
+                    e
+                );
             }
+        }
+        if !is_chinese && session.audio {
+            Self::play(pronounce);
+        }
+        Ok(Some(RoundRecord::new(
+            mode,
+            if is_chinese {
+                Direction::ChineseToEnglish
+            } else {
+                Direction::EnglishToChinese
+            },
+            word.key(),
+            &answer,
+            response_time,
+        )))
+    }
+
+    fn tone_round(
+        terminal: &mut Term,
+        syllable: &Syllable,
+        pronounce: &Pronouncation,
+        session: &Session,
+    ) -> Result<Option<RoundRecord>, Error> {
+        terminal.write_question(Syllable::question())?;
+        if session.audio {
+            thread::sleep(std::time::Duration::from_millis(720));
+            Self::play(pronounce);
+        }
+
+        let started = Instant::now();
+        let reply = Self::read_reply(terminal, pronounce, &syllable.hint(), None)?;
+        let response_time = started.elapsed();
+        let answer = match reply {
+            Reply::Quit => return Ok(None),
+            Reply::Skip => syllable.skip(),
+            Reply::Answer(input) => syllable.handle_response(&input),
+        };
+        terminal.write_styled_line(&answer.feedback)?;
+        Ok(Some(RoundRecord::new(
+            Mode::Tones.name(),
+            Direction::Tone,
+            syllable.numbered(),
+            &answer,
+            response_time,
+        )))
+    }
+
+    /// Plays the pronunciation, warning instead of failing the round.
+    fn play(pronounce: &Pronouncation) {
+        if let Err(e) = pronounce.play_all() {
+            log::warn!("Could not play audio: {}", e);
+        }
+    }
 
+    /// Reads lines until an answer is given, handling the round commands in between.
+    fn read_reply(
+        terminal: &mut Term,
+        pronounce: &Pronouncation,
+        hint: &str,
+        notes: Option<&str>,
+    ) -> Result<Reply, Error> {
+        loop {
             let input = terminal.read_line()?;
-            let response = if is_chinese {
-                word.handle_chinese_response(&input)
-            } else {
-                word.handle_english_response(&input)
-            };
+            match RoundCommand::parse(&input) {
+                None => return Ok(Reply::Answer(input)),
+                Some(RoundCommand::Skip) => return Ok(Reply::Skip),
+                Some(RoundCommand::Quit) => return Ok(Reply::Quit),
+                Some(RoundCommand::Replay) => Self::play(pronounce),
+                Some(RoundCommand::Hint) => terminal.write_styled_line(hint)?,
+                Some(RoundCommand::Notes) => terminal.write_styled_line(notes.map_or_else(
+                    || style("There are no notes.").dim().to_string(),
+                    str::to_string,
+                ))?,
+            }
+            terminal.write_styled("> ")?;
+        }
+    }
+}
 
-            terminal.write_line(&response)?;
-            if !is_chinese {
-                pronounce.play_all().unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_weights_parse() {
+        assert_eq!(
+            RandomWeights::parse("words=1, sentences = 2,tones=0").unwrap(),
+            RandomWeights {
+                words: 1,
+                phrases: 0,
+                sentences: 2,
+                tones: 0,
             }
+        );
+        assert!(RandomWeights::parse("words=0").is_err());
+        assert!(RandomWeights::parse("verbs=1").is_err());
+        assert!(RandomWeights::parse("words").is_err());
+    }
+
+    #[test]
+    fn test_round_command_parse() {
+        assert_eq!(RoundCommand::parse(":r"), Some(RoundCommand::Replay));
+        assert_eq!(RoundCommand::parse(" :Q\n"), Some(RoundCommand::Quit));
+        assert_eq!(RoundCommand::parse(":skip"), Some(RoundCommand::Skip));
+        assert_eq!(RoundCommand::parse("r"), None);
+        assert_eq!(RoundCommand::parse("hello"), None);
+    }
+
+    #[test]
+    fn test_random_weights_pick() {
+        let weights = RandomWeights::parse("sentences=1").unwrap();
+        for _ in 0..10 {
+            assert!(matches!(weights.pick(), Mode::Sentences));
         }
-        Ok(())
+    }
+
+    #[test]
+    fn test_mode_audible() {
+        assert!(Mode::Tones.audible(false).is_none());
+        assert!(matches!(Mode::Tones.audible(true), Some(Mode::Tones)));
+        assert!(matches!(Mode::Words.audible(false), Some(Mode::Words)));
+
+        let weights = RandomWeights::default();
+        let Some(Mode::Random(silent)) = Mode::Random(weights.clone()).audible(false) else {
+            panic!("random mode without tones should be playable");
+        };
+        assert_eq!(silent.tones, 0);
+        assert_eq!(silent.words, weights.words);
+        let tones_only = RandomWeights::parse("tones=1").unwrap();
+        assert!(Mode::Random(tones_only).audible(false).is_none());
     }
 }
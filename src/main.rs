@@ -1,47 +1,88 @@
 #![warn(clippy::perf, clippy::pedantic, clippy::nursery)]
 
+mod cli;
+mod config;
+mod deck;
+mod dictionary;
+mod error;
 mod file_io;
 mod game;
 mod models;
+mod pinyin;
+mod sandhi;
+mod scheduler;
+mod stats;
+mod tone_archive;
+mod tts;
 mod utils;
+mod zhuyin;
 
 use anyhow::Error;
+use clap::Parser;
+use cli::{Cli, Command, ConfigCommand, DeckCommand};
+use config::Config;
 use console::style;
 use console::Term;
+use dictionary::Dictionary;
 use game::Language;
-use lazy_static::lazy_static;
 use models::BaseModel;
+use stats::History;
 use std::env;
-use std::io::Write;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use tone_archive::ToneArchive;
+use utils::StyledWrite;
 
-lazy_static! {
-    static ref WORDS: Box<[BaseModel]> = {
-        match file_io::read_json("files/words.json") {
-            Ok(phrases) => phrases,
+const WORDS_FILE: &str = "words.json";
+const PHRASES_FILE: &str = "phrases.json";
+const SENTENCES_FILE: &str = "sentences.json";
+
+static DICTIONARY: LazyLock<Dictionary> =
+    LazyLock::new(
+        || match Dictionary::load(&data_path(""), &file_io::cache_dir()) {
+            Ok(dictionary) => dictionary,
             Err(e) => {
-                log::error!("Error reading phrases: {}", e);
+                log::error!("Error reading dictionary: {}", e);
                 std::process::exit(1);
             }
+        },
+    );
+static TONE_ARCHIVE: LazyLock<ToneArchive> =
+    LazyLock::new(|| ToneArchive::load(&data_path(""), &file_io::cache_dir()));
+static WORDS: LazyLock<Box<[BaseModel]>> =
+    LazyLock::new(|| match file_io::read_json(&data_path(WORDS_FILE)) {
+        Ok(phrases) => phrases,
+        Err(e) => {
+            log::error!("Error reading phrases: {}", e);
+            std::process::exit(1);
         }
-    };
-    static ref PHRASES: Box<[BaseModel]> = {
-        match file_io::read_json("files/phrases.json") {
-            Ok(phrases) => phrases,
-            Err(e) => {
-                log::error!("Error reading phrases: {}", e);
-                std::process::exit(1);
-            }
+    });
+static PHRASES: LazyLock<Box<[BaseModel]>> =
+    LazyLock::new(|| match file_io::read_json(&data_path(PHRASES_FILE)) {
+        Ok(phrases) => phrases,
+        Err(e) => {
+            log::error!("Error reading phrases: {}", e);
+            std::process::exit(1);
         }
-    };
-    static ref SENTENCES: Box<[BaseModel]> = {
-        match file_io::read_json("files/sentences.json") {
-            Ok(phrases) => phrases,
-            Err(e) => {
-                log::error!("Error reading phrases: {}", e);
-                std::process::exit(1);
-            }
+    });
+static SENTENCES: LazyLock<Box<[BaseModel]>> =
+    LazyLock::new(|| match file_io::read_json(&data_path(SENTENCES_FILE)) {
+        Ok(phrases) => phrases,
+        Err(e) => {
+            log::error!("Error reading phrases: {}", e);
+            std::process::exit(1);
         }
-    };
+    });
+
+/// Path of a file in the data directory, exits if there is none.
+fn data_path(file_name: &str) -> PathBuf {
+    match file_io::data_dir() {
+        Ok(dir) => dir.join(file_name),
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    }
 }
 
 fn start_text() -> String {
@@ -51,7 +92,9 @@ fn start_text() -> String {
             2. Phrases\n\
             3. Sentences\n\
             4. Tones \n\
-            5. Random\n\n",
+            5. Random\n\
+            6. Stats\n\
+            7. Dictionary\n\n",
     )
     .cyan();
     format!(
@@ -69,16 +112,14 @@ fn invalid_selection() -> String {
     )
 }
 
-fn not_implemented_yet() -> String {
-    format!("{}", style("Not implemented yet.").red())
-}
-
 enum GameMode {
     Words = 1,
     Phrases = 2,
     Sentences = 3,
     Tones = 4,
     Random = 5,
+    Stats = 6,
+    Dictionary = 7,
 }
 
 impl GameMode {
@@ -89,6 +130,8 @@ impl GameMode {
             "3" | "sentences" => Some(Self::Sentences),
             "4" | "tones" => Some(Self::Tones),
             "5" | "random" => Some(Self::Random),
+            "6" | "stats" => Some(Self::Stats),
+            "7" | "dictionary" => Some(Self::Dictionary),
             _ => None,
         }
     }
@@ -105,37 +148,87 @@ fn main() -> Result<(), Error> {
         }
     }
 
+    let cli = Cli::parse();
     dotenv::dotenv().ok();
     env_logger::init();
+    if let Some(dir) = cli.data_dir {
+        file_io::set_data_dir_flag(dir);
+    }
+    if cli.command.as_ref().is_none_or(Command::needs_data) {
+        file_io::data_dir()?;
+    }
 
+    let config = Config::load();
     let mut terminal = Term::stdout();
-    terminal.write_all(start_text().as_bytes())?;
+    match cli.command {
+        None => menu(&mut terminal, &config),
+        Some(Command::Play {
+            mode,
+            direction,
+            count,
+        }) => {
+            let mut session = config.session();
+            session.direction = direction.and_then(cli::DirectionArg::direction);
+            session.count = count.or(session.count);
+            Language::run(
+                &mut terminal,
+                mode.mode(&config.random_weights),
+                config.answer_options(),
+                &session,
+                &mut History::load_default(),
+            )
+        }
+        Some(Command::Lookup { query, play }) => {
+            cli::lookup(&mut terminal, &query.join(" "), play, &config)
+        }
+        Some(Command::Stats) => cli::stats(&terminal),
+        Some(Command::Index) => cli::build_indexes(&mut terminal),
+        Some(Command::Deck {
+            command: DeckCommand::Validate { paths },
+        }) => cli::validate_decks(&mut terminal, &paths),
+        Some(Command::Config { command }) => cli::config(
+            &mut terminal,
+            command.unwrap_or(ConfigCommand::Show),
+            &config,
+        ),
+    }
+}
+
+/// The interactive menu, shown when no subcommand is given.
+fn menu(terminal: &mut Term, config: &Config) -> Result<(), Error> {
+    // Load the dictionary in the background while the menu is shown
+    std::thread::spawn(|| LazyLock::force(&DICTIONARY));
+
+    let mut history = History::load_default();
+    let options = config.answer_options();
+    let session = config.session();
+    terminal.write_styled(start_text())?;
 
     '_main: loop {
         let input = terminal.read_line()?;
         let Some(game_mode) = GameMode::from_str(&input) else {
-            terminal.write_all(invalid_selection().as_bytes())?;
+            terminal.write_styled(invalid_selection())?;
             continue '_main;
         };
 
-        match game_mode {
-            GameMode::Words => {
-                Language::run(&mut terminal, game::Mode::Words)?;
-            }
-            GameMode::Phrases => {
-                Language::run(&mut terminal, game::Mode::Phrases)?;
-            }
-            GameMode::Sentences => {
-                Language::run(&mut terminal, game::Mode::Sentences)?;
+        let mode = match game_mode {
+            GameMode::Words => game::Mode::Words,
+            GameMode::Phrases => game::Mode::Phrases,
+            GameMode::Sentences => game::Mode::Sentences,
+            GameMode::Tones => game::Mode::Tones,
+            GameMode::Random => game::Mode::Random(config.random_weights.clone()),
+            GameMode::Stats => {
+                terminal.write_line(&history.report())?;
+                terminal.write_styled(start_text())?;
+                continue '_main;
             }
-            GameMode::Tones => {
-                terminal.write_line(&not_implemented_yet())?;
-                terminal.write_all(invalid_selection().as_bytes())?;
+            GameMode::Dictionary => {
+                cli::browse(terminal, config)?;
+                terminal.write_styled(start_text())?;
+                continue '_main;
             }
-            GameMode::Random => {
-                terminal.write_line(&not_implemented_yet())?;
-                terminal.write_all(invalid_selection().as_bytes())?;
-            }
-        }
+        };
+        Language::run(terminal, mode, options, &session, &mut history)?;
+        terminal.write_styled(start_text())?;
     }
 }
@@ -1,10 +1,23 @@
-use super::file_io::{get_audio_file_from_compressed_archive, get_pinyin_from_compressed_json};
-use super::utils::string::{match_tone, normalize_char, normalize_word};
-use console::style;
-use rand::{random, Rng};
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use super::dictionary::Token;
+use super::error::AudioError;
+use super::game::AnswerOptions;
+use super::pinyin;
+use super::sandhi;
+use super::tts::{ArchiveBackend, Clip, Speaker, Utterance};
+use super::utils::render_markup;
+use super::utils::string::{
+    compare_english, compare_pinyin, is_hanzi, mask_letters, match_tone, normalize_char,
+    normalize_word, retone, strip_non_hanzi, EnglishMatch,
+};
+use super::zhuyin;
+use super::{DICTIONARY, TONE_ARCHIVE};
+use console::{style, StyledObject};
+use rand::random;
+use rand::seq::SliceRandom;
+use rodio::Source;
 use serde::{Deserialize, Serialize};
 use std::io::{BufReader, Cursor};
+use std::time::Duration;
 
 #[derive(Serialize, Deserialize)]
 pub struct BaseModelDto {
@@ -13,6 +26,10 @@ pub struct BaseModelDto {
     pub(super) english: Box<[Box<str>]>,
     // extra
     pub(super) notes: Option<Box<[String]>>,
+    /// Pinyin of each word, used instead of the dictionary for words with
+    /// several readings, e.g. `["yín háng"]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) pinyin: Option<Box<[Box<str>]>>,
 }
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub struct BaseModel {
@@ -21,10 +38,12 @@ pub struct BaseModel {
     pub(super) english: Box<[Box<str>]>,
     // extra
     pub(super) notes: Option<Box<[String]>>,
+    pinyin_override: Option<Box<[Box<str>]>>,
 
     // hidden attributes
     pinyin_fetched: bool,
     pinyin: Option<Vec<String>>,
+    script: Script,
 }
 pub trait ToBaseModel {
     fn to_base_model(&self) -> BaseModel;
@@ -38,11 +57,24 @@ impl ToBaseModel for BaseModelDto {
             self.english.clone(),
             self.notes.clone(),
         )
+        .with_pinyin(self.pinyin.clone())
+    }
+}
+
+impl ToBaseModel for DictObject {
+    fn to_base_model(&self) -> BaseModel {
+        BaseModel::new(
+            Box::new([self.traditional.clone()]),
+            Box::new([self.simplified.clone()]),
+            Box::new([self.english.clone()]),
+            None,
+        )
+        .with_pinyin(Some(Box::new([self.pinyin.clone()])))
     }
 }
 
 impl BaseModel {
-    pub(super) fn new(
+    pub(super) const fn new(
         traditional: Box<[Box<str>]>,
         simplified: Box<[Box<str>]>,
         english: Box<[Box<str>]>,
@@ -53,43 +85,95 @@ impl BaseModel {
             simplified,
             english,
             notes,
+            pinyin_override: None,
             pinyin_fetched: false,
             pinyin: None,
+            script: Script::Traditional,
+        }
+    }
+
+    /// Uses the given pinyin instead of the dictionary if there is one per word,
+    /// written with tone marks or numbers and with or without spaces.
+    pub(super) fn with_pinyin(mut self, pinyin: Option<Box<[Box<str>]>>) -> Self {
+        self.pinyin_override = pinyin
+            .filter(|pinyin| pinyin.len() == self.traditional.len())
+            .map(|pinyin| {
+                pinyin
+                    .iter()
+                    .map(|word| {
+                        let syllables = pinyin::segment(word)
+                            .map_or_else(|| word.to_string(), |syllables| syllables.join(" "));
+                        pinyin::to_marked(&syllables).into()
+                    })
+                    .collect()
+            });
+        self.pinyin_fetched = false;
+        self.pinyin = None;
+        self
+    }
+
+    /// Sets the script the word is shown in, looked up in the dictionary with and
+    /// checked against.
+    pub fn set_script(&mut self, script: Script) {
+        if self.script != script {
+            self.script = script;
+            self.pinyin_fetched = false;
+            self.pinyin = None;
         }
     }
 
-    /// Fetches the pinyin for the traditional characters.
+    /// The words in the chosen script, traditional when showing both.
+    pub(super) fn characters(&self) -> &[Box<str>] {
+        match self.script {
+            Script::Simplified => &self.simplified,
+            Script::Traditional | Script::Both => &self.traditional,
+        }
+    }
+
+    /// Key identifying the item in the learner state files, e.g. `了 (liǎo)`.
+    ///
+    /// Without a pinyin override the reading follows from the characters, so
+    /// only overrides are added to tell apart the readings of a polyphonic word.
+    pub fn key(&self) -> String {
+        self.pinyin_override.as_ref().map_or_else(
+            || self.characters_key(),
+            |pinyin| format!("{} ({})", self.characters_key(), pinyin.join(",")),
+        )
+    }
+
+    /// Key of the item before pinyin overrides were part of it, see
+    /// [`Scheduler::migrate_keys`](super::scheduler::Scheduler::migrate_keys).
+    pub fn characters_key(&self) -> String {
+        self.traditional.join(",")
+    }
+
+    /// Fetches the pinyin for the characters in the chosen script.
     /// Returns a vector of strings where each string is the pinyin for a word.
     ///
-    /// Uses cached values if already fetched.
+    /// Uses the pinyin of the deck entry if it has one and cached values if
+    /// already fetched.
     pub fn pinyin(&mut self) -> Vec<String> {
         let ret;
         if self.pinyin_fetched {
             ret = self.pinyin.as_ref().unwrap().clone();
         } else {
-            // Try to get pinyins for the whole sentence
-            let mut pinyins = self
-                .traditional
-                .iter()
-                .filter_map(|w| get_pinyin_from_compressed_json(w).map(|v| v.to_lowercase()))
-                .peekable();
-
-            ret = if pinyins.peek().is_none() {
-                // If not found, then get pinyins for each individual char
-                self.traditional
-                    .iter()
-                    .map(|w| {
-                        w.chars()
-                            .filter_map(|c| {
-                                get_pinyin_from_compressed_json(&c).map(|v| v.to_lowercase())
-                            })
-                            .collect::<Vec<String>>()
-                            .join(" ")
-                    })
-                    .collect::<Vec<_>>()
-            } else {
-                pinyins.collect()
-            };
+            // Prefer whole dictionary words over single characters, see `segment_pinyin`
+            ret = self
+                .pinyin_override
+                .as_ref()
+                .map_or_else(
+                    || {
+                        self.characters()
+                            .iter()
+                            .map(|w| DICTIONARY.segment_pinyin(w, self.script))
+                            .collect::<Vec<_>>()
+                    },
+                    |pinyin| pinyin.iter().map(ToString::to_string).collect(),
+                )
+                .into_iter()
+                // Also moves marks the dictionary puts on the wrong vowel, e.g. `zùo`
+                .map(|v| pinyin::to_marked(&v.to_lowercase()))
+                .collect::<Vec<_>>();
 
             self.pinyin = Some(ret.clone());
             self.pinyin_fetched = true;
@@ -97,10 +181,6 @@ impl BaseModel {
         ret
     }
 
-    fn pinyin_alphabet(&mut self) -> Vec<String> {
-        self.pinyin().iter().map(normalize_word).collect()
-    }
-
     pub fn tones(&mut self) -> Vec<String> {
         self.pinyin()
             .iter()
@@ -127,20 +207,189 @@ impl BaseModel {
             .collect()
     }
 
-    pub fn has_normal_tones(&mut self) -> bool {
-        self.tones().iter().any(|sentence| {
-            sentence
-                .split_whitespace()
-                .any(|word| !word.chars().last().unwrap().is_numeric())
+    /// Each syllable's character and citation tone per word, split into the
+    /// clauses between punctuation. A word that does not match its pinyin is
+    /// a single clause with spaces for its characters.
+    fn citation_tones(&mut self) -> Vec<Vec<Vec<(char, u8)>>> {
+        let characters = self.characters().to_vec();
+        self.pinyin()
+            .iter()
+            .zip(characters.iter())
+            .map(|(pinyin, word)| {
+                let tones = pinyin
+                    .split_whitespace()
+                    .map(|syllable| {
+                        let tone = syllable.chars().map(match_tone).find(|tone| *tone != 5);
+                        tone.unwrap_or(5)
+                    })
+                    .collect::<Vec<_>>();
+                let clauses = word
+                    .split(|c: char| !is_hanzi(c) && !c.is_whitespace())
+                    .map(|clause| clause.chars().filter(|c| is_hanzi(*c)).collect::<Vec<_>>())
+                    .filter(|clause| !clause.is_empty())
+                    .collect::<Vec<_>>();
+                if clauses.iter().map(Vec::len).sum::<usize>() != tones.len() {
+                    return vec![vec![' '; tones.len()].into_iter().zip(tones).collect()];
+                }
+                let mut tones = tones.into_iter();
+                clauses
+                    .into_iter()
+                    .map(|clause| clause.into_iter().zip(tones.by_ref()).collect())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Applies the spoken tones to the syllables of each word, see [`sandhi::spoken_tones`].
+    fn respell(&mut self, words: &[String], retone: impl Fn(&str, u8) -> String) -> Vec<String> {
+        words
+            .iter()
+            .zip(self.citation_tones())
+            .map(|(word, clauses)| {
+                // Tones only change within a clause, not across punctuation
+                let spoken = clauses
+                    .iter()
+                    .flat_map(|clause| sandhi::spoken_tones(clause));
+                word.split_whitespace()
+                    .zip(spoken)
+                    .map(|(syllable, tone)| retone(syllable, tone))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    /// The pinyin as spoken, e.g. `ní hǎo` for 你好.
+    pub fn spoken_pinyin(&mut self) -> Vec<String> {
+        let pinyin = self.pinyin();
+        self.respell(&pinyin, retone)
+    }
+
+    /// The numbered syllables as spoken, e.g. `ni2 hao3` for 你好.
+    pub fn spoken_tones(&mut self) -> Vec<String> {
+        let tones = self.tones();
+        self.respell(&tones, |syllable, tone| {
+            let base = syllable.trim_end_matches(|c: char| c.is_ascii_digit());
+            if base.len() == syllable.len() {
+                // Neutral tone syllables stay unnumbered
+                base.to_string()
+            } else {
+                format!("{base}{tone}")
+            }
         })
     }
 
-    fn styled_pinyin(&mut self) -> String {
-        self.pinyin()
+    /// Splits the characters into syllables paired with their pinyin.
+    /// Words whose character count does not match the pinyin syllable count are skipped.
+    pub fn syllables(&mut self) -> Vec<Syllable> {
+        self.characters()
+            .to_vec()
             .iter()
-            .map(|word| style(word).cyan().to_string())
-            .collect::<Vec<_>>()
-            .join(", ")
+            .zip(self.pinyin())
+            .filter_map(|(word, pinyin)| {
+                let chars = word.chars().collect::<Vec<_>>();
+                let pinyins = pinyin.split_whitespace().collect::<Vec<_>>();
+                if chars.len() != pinyins.len() {
+                    return None;
+                }
+                Some(
+                    chars
+                        .into_iter()
+                        .zip(pinyins)
+                        .map(|(character, pinyin)| Syllable::new(character, pinyin))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// The pinyin of each word, or its zhuyin if `in_zhuyin` is set.
+    fn phonetic(pinyin: Vec<String>, in_zhuyin: bool) -> Vec<String> {
+        if in_zhuyin {
+            pinyin
+                .iter()
+                .map(|word| zhuyin::from_pinyin_text(word))
+                .collect()
+        } else {
+            pinyin
+        }
+    }
+
+    /// The pinyin of a sentence grouped into words with its punctuation, e.g.
+    /// `Jīntiān tiānqì hěn hǎo.` for 今天天氣很好。, or its zhuyin with the
+    /// syllables of each word run together. Characters without pinyin are kept.
+    fn sentence_pinyin(&self, sentence: &str, pinyin: &str, in_zhuyin: bool) -> String {
+        let mut syllables = pinyin.split_whitespace().collect::<Vec<_>>().into_iter();
+        // Pinyin from the dictionary leaves out the characters it has no entry for
+        let per_character = syllables.len() == sentence.chars().filter(|c| is_hanzi(*c)).count();
+        let mut text = String::new();
+        // Full-width punctuation in zhuyin needs no space after it
+        let mut after_punctuation = false;
+        for token in DICTIONARY.tokenize(sentence, self.script) {
+            let is_punctuation = matches!(token, Token::Other(_));
+            let (piece, spaced) = match token {
+                Token::Word { text, pinyin } if per_character || pinyin.is_some() => {
+                    let word = syllables
+                        .by_ref()
+                        .take(text.chars().count())
+                        .collect::<Vec<_>>();
+                    let piece = if in_zhuyin {
+                        word.iter()
+                            .map(|syllable| zhuyin::from_pinyin_text(syllable))
+                            .collect()
+                    } else {
+                        pinyin::join(&word)
+                    };
+                    (piece, true)
+                }
+                Token::Word { text, .. } => (text, true),
+                Token::Other(other) => {
+                    let converted = other.chars().map(pinyin::punctuation).collect::<String>();
+                    let spaced = !converted.starts_with(['.', ',', '?', '!', ':', ';', ')']);
+                    (if in_zhuyin { other } else { converted }, spaced)
+                }
+            };
+            let glued = in_zhuyin && after_punctuation;
+            if spaced && !glued && !text.is_empty() && !text.ends_with('(') {
+                text.push(' ');
+            }
+            text.push_str(&piece);
+            after_punctuation = is_punctuation;
+        }
+        if in_zhuyin {
+            text
+        } else {
+            pinyin::capitalize(&text)
+        }
+    }
+
+    /// The pinyin of each word, grouped into words for sentences, or its zhuyin.
+    /// Words are separated by commas, sentences ending in punctuation by a space.
+    fn styled_pinyin(&mut self, in_zhuyin: bool) -> String {
+        let characters = self.characters().to_vec();
+        let (mut text, mut previous) = (String::new(), None::<String>);
+        for (pinyin, characters) in self.pinyin().iter().zip(characters.iter()) {
+            let word = if characters
+                .chars()
+                .any(|c| !is_hanzi(c) && !c.is_whitespace())
+            {
+                self.sentence_pinyin(characters, pinyin, in_zhuyin)
+            } else {
+                Self::phonetic(vec![pinyin.clone()], in_zhuyin).concat()
+            };
+            if let Some(previous) = &previous {
+                let ends_sentence = previous.ends_with(['.', '?', '!', '。', '？', '！']);
+                text.push_str(match (ends_sentence, in_zhuyin) {
+                    (true, true) => "",
+                    (true, false) => " ",
+                    (false, _) => ", ",
+                });
+            }
+            text.push_str(&style(&word).cyan().to_string());
+            previous = Some(word);
+        }
+        text
     }
 
     fn styled_english(&self) -> String {
@@ -151,77 +400,312 @@ impl BaseModel {
             .join(", ")
     }
 
-    fn styled_traditional(&self) -> String {
-        self.traditional
+    /// The characters in the chosen script, e.g. `我愛你 (我爱你)` when showing both.
+    /// Shows only the traditional words if the simplified ones do not pair up with them.
+    fn styled_characters(&self) -> String {
+        let words = match self.script {
+            Script::Both if self.traditional.len() == self.simplified.len() => self
+                .traditional
+                .iter()
+                .zip(self.simplified.iter())
+                .map(|(traditional, simplified)| {
+                    if traditional == simplified {
+                        traditional.to_string()
+                    } else {
+                        format!("{traditional} ({simplified})")
+                    }
+                })
+                .collect::<Vec<_>>(),
+            _ => self.characters().iter().map(ToString::to_string).collect(),
+        };
+        words
             .iter()
             .map(|word| style(word).cyan().to_string())
             .collect::<Vec<_>>()
             .join(", ")
     }
 
-    fn pinyin_alphabet_trimmed(&mut self) -> Vec<String> {
-        self.pinyin_alphabet()
-            .iter()
-            .map(|w| w.to_lowercase().trim().replace(' ', ""))
-            .collect()
-    }
-
-    fn english_trimmed(&self) -> Vec<String> {
-        self.english
-            .iter()
-            .map(|w| w.trim().to_lowercase())
-            .collect()
-    }
-
-    pub(super) fn question_chinese(&mut self) -> String {
+    /// Asks for the translation, showing the spoken tones next to the
+    /// dictionary tones where they differ if `spoken_tones` is set.
+    pub(super) fn question_chinese(&mut self, options: AnswerOptions) -> String {
+        let mut pinyin = self.styled_pinyin(options.zhuyin);
+        let spoken = self.spoken_pinyin();
+        if options.spoken_tones && spoken != self.pinyin() {
+            pinyin = format!(
+                "{pinyin} (spoken {})",
+                style(Self::phonetic(spoken, options.zhuyin).join(", "))
+                    .cyan()
+                    .dim()
+            );
+        }
         format!(
             "Here is a word in Chinese {}, {}. What is it in English?",
-            self.styled_pinyin(),
-            self.styled_traditional()
+            pinyin,
+            self.styled_characters()
         )
     }
 
-    pub(super) fn question_english(&self) -> String {
+    pub(super) fn question_english(&self, require_characters: bool) -> String {
         format!(
-            "Here is a word in English {}. What is it in Chinese?",
-            self.styled_english()
+            "Here is a word in English {}. What is it in {}?",
+            self.styled_english(),
+            if require_characters {
+                "Chinese characters"
+            } else {
+                "Chinese"
+            }
         )
     }
 
-    pub(super) fn handle_chinese_response(&self, english_res: &str) -> String {
-        let txt = if self
-            .english_trimmed()
-            .contains(&english_res.trim().to_lowercase())
+    pub(super) fn handle_chinese_response(
+        &self,
+        english_res: &str,
+        options: AnswerOptions,
+    ) -> Answer {
+        let verdict = match self
+            .english
+            .iter()
+            .map(|english| compare_english(english, english_res))
+            .max()
         {
-            style("Correct! Well done!").green()
+            Some(EnglishMatch::Exact) => Verdict::Correct,
+            Some(EnglishMatch::Close) => Verdict::Close,
+            _ => Verdict::Wrong,
+        };
+
+        self.english_answer(verdict, options)
+    }
+
+    fn english_answer(&self, verdict: Verdict, options: AnswerOptions) -> Answer {
+        let feedback = format!(
+            "{}. The English translation is: {}\n{}",
+            verdict.styled(),
+            self.styled_english(),
+            self.notes_feedback(options)
+        );
+        Answer { verdict, feedback }
+    }
+
+    /// Checks an answer written in Chinese characters of the chosen script,
+    /// in pinyin with tone numbers, tone marks or without tones, or in zhuyin.
+    ///
+    /// With `strict_tones` a pinyin answer must contain the correct tones,
+    /// otherwise wrong tones are only reported. With `require_characters`
+    /// pinyin answers are not accepted.
+    pub(super) fn handle_english_response(
+        &mut self,
+        chinese_res: &str,
+        options: AnswerOptions,
+    ) -> Answer {
+        let (verdict, note) = if chinese_res.chars().any(is_hanzi) {
+            (self.check_characters(chinese_res), String::new())
+        } else if options.require_characters {
+            (
+                Verdict::Wrong,
+                format!(
+                    "{}\n",
+                    style("The answer needs to be written in Chinese characters.").yellow()
+                ),
+            )
+        } else if chinese_res.chars().any(zhuyin::is_zhuyin) {
+            zhuyin::to_pinyin_text(chinese_res).map_or_else(
+                || {
+                    (
+                        Verdict::Wrong,
+                        format!("{}\n", style("The answer is not valid zhuyin.").yellow()),
+                    )
+                },
+                |pinyin| self.check_pinyin(&pinyin, options),
+            )
         } else {
-            style("Wrong!").red()
+            self.check_pinyin(chinese_res, options)
         };
 
-        format!(
-            "{}. The English translation is: {}\n",
-            txt,
-            self.styled_english()
-        )
+        self.chinese_answer(verdict, &note, options)
     }
 
-    pub(super) fn handle_english_response(&mut self, pinyin_res: &str) -> String {
-        let txt = if self
-            .pinyin_alphabet_trimmed()
-            .contains(&pinyin_res.trim().to_lowercase().replace(' ', ""))
-        {
-            style("Correct! Well done!").green()
+    fn chinese_answer(&mut self, verdict: Verdict, note: &str, options: AnswerOptions) -> Answer {
+        let feedback = format!(
+            "{}. The Chinese translation is: {}, {}\n{}{}",
+            verdict.styled(),
+            self.styled_pinyin(options.zhuyin),
+            self.styled_characters(),
+            note,
+            self.notes_feedback(options)
+        );
+        Answer { verdict, feedback }
+    }
+
+    /// Reveals the translation without an answer.
+    pub(super) fn skip(&mut self, is_chinese: bool, options: AnswerOptions) -> Answer {
+        if is_chinese {
+            self.english_answer(Verdict::Skipped, options)
         } else {
-            style("Wrong!").red()
+            self.chinese_answer(Verdict::Skipped, "", options)
+        }
+    }
+
+    /// The notes shown below the verdict, unless they are only shown on demand.
+    fn notes_feedback(&self, options: AnswerOptions) -> String {
+        if options.notes_on_demand {
+            return String::new();
+        }
+        self.styled_notes()
+            .map_or_else(String::new, |notes| format!("{notes}\n"))
+    }
+
+    /// A hint towards the answer: the first letter of each word of the English
+    /// translation, or of each syllable of the toneless pinyin or zhuyin.
+    pub(super) fn hint(&mut self, is_chinese: bool, in_zhuyin: bool) -> String {
+        let hint = if is_chinese {
+            mask_letters(self.english.first().map_or("", |english| english))
+        } else if in_zhuyin {
+            self.pinyin()
+                .iter()
+                .map(|word| mask_letters(&zhuyin::without_tones(&zhuyin::from_pinyin_text(word))))
+                .collect::<Vec<_>>()
+                .join(", ")
+        } else {
+            self.pinyin()
+                .iter()
+                .map(|word| mask_letters(&normalize_word(word)))
+                .collect::<Vec<_>>()
+                .join(", ")
         };
+        format!("Hint: {}", style(hint).cyan())
+    }
 
-        format!(
-            "{}. The Chinese translation is: {}, {}\n",
-            txt,
-            self.styled_pinyin(),
-            self.styled_traditional()
+    /// The notes of the word as a list, with their markup rendered.
+    pub(super) fn styled_notes(&self) -> Option<String> {
+        let notes = self
+            .notes
+            .iter()
+            .flatten()
+            .filter(|note| !note.trim().is_empty())
+            .map(|note| format!("  • {}", render_markup(note.trim())))
+            .collect::<Vec<_>>();
+        if notes.is_empty() {
+            return None;
+        }
+        Some(format!("{}\n{}", style("Notes:").bold(), notes.join("\n")))
+    }
+
+    fn check_characters(&self, characters_res: &str) -> Verdict {
+        let answer = strip_non_hanzi(characters_res);
+        Verdict::from(
+            self.traditional
+                .iter()
+                .filter(|_| self.script != Script::Simplified)
+                .chain(
+                    self.simplified
+                        .iter()
+                        .filter(|_| self.script != Script::Traditional),
+                )
+                .any(|word| strip_non_hanzi(word) == answer),
         )
     }
+
+    /// Returns the verdict and a note about the tones of the answer.
+    fn check_pinyin(&mut self, pinyin_res: &str, options: AnswerOptions) -> (Verdict, String) {
+        let strict_tones = options.strict_tones;
+        let marked = self.pinyin();
+        let pinyin = Self::phonetic(marked.clone(), options.zhuyin);
+        let best = self
+            .tones()
+            .iter()
+            .map(|expected| compare_pinyin(expected, pinyin_res))
+            .enumerate()
+            .filter(|(_, check)| check.letters_match)
+            .min_by_key(|(_, check)| (check.wrong_tones.len(), !check.has_tones));
+
+        let verdict = Verdict::from(best.as_ref().is_some_and(|(_, check)| {
+            !strict_tones || (check.has_tones && check.wrong_tones.is_empty())
+        }));
+
+        let tone_note = best.map_or_else(String::new, |(i, check)| {
+            if strict_tones && !check.has_tones {
+                let example = format!(
+                    "The answer needs tones, e.g. {} or {}.",
+                    pinyin::to_numbered(&marked[i]),
+                    marked[i]
+                );
+                format!("{}\n", style(example).yellow())
+            } else if check.has_tones && !check.wrong_tones.is_empty() {
+                let syllables = pinyin[i].split_whitespace().collect::<Vec<_>>();
+                let wrong = check
+                    .wrong_tones
+                    .iter()
+                    .filter_map(|s| syllables.get(*s))
+                    .map(|syllable| style(syllable).yellow().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("Wrong tone on: {wrong}\n")
+            } else {
+                String::new()
+            }
+        });
+        (verdict, tone_note)
+    }
+}
+
+/// Which Chinese characters are shown and accepted.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Script {
+    #[default]
+    Traditional,
+    Simplified,
+    /// Shows both forms and accepts either
+    Both,
+}
+
+impl Script {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "traditional" | "trad" => Some(Self::Traditional),
+            "simplified" | "simp" => Some(Self::Simplified),
+            "both" => Some(Self::Both),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of checking a response together with the feedback shown to the learner.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Answer {
+    pub(super) verdict: Verdict,
+    pub(super) feedback: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Verdict {
+    Correct,
+    /// Almost right, e.g. a missing word in a sentence
+    Close,
+    Wrong,
+    /// Revealed without answering
+    Skipped,
+}
+
+impl From<bool> for Verdict {
+    fn from(correct: bool) -> Self {
+        if correct {
+            Self::Correct
+        } else {
+            Self::Wrong
+        }
+    }
+}
+
+impl Verdict {
+    pub fn styled(self) -> StyledObject<&'static str> {
+        match self {
+            Self::Correct => style("Correct! Well done!").green(),
+            Self::Close => style("Close!").yellow(),
+            Self::Skipped => style("Skipped").yellow(),
+            Self::Wrong => style("Wrong!").red(),
+        }
+    }
 }
 
 pub fn _get_base_model() -> BaseModel {
@@ -242,7 +726,71 @@ pub fn _get_base_model() -> BaseModel {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Syllable {
+    pub(super) character: char,
+    pub(super) pinyin: String,
+    pub(super) tone: u8,
+}
+
+impl Syllable {
+    pub(super) fn new(character: char, pinyin: &str) -> Self {
+        let tone = pinyin
+            .chars()
+            .map(match_tone)
+            .find(|tone| *tone != 5)
+            .unwrap_or(5);
+        Self {
+            character,
+            pinyin: pinyin.to_lowercase(),
+            tone,
+        }
+    }
+
+    /// Returns the syllable in numbered form, e.g. `hao3` or `lü4`.
+    pub fn numbered(&self) -> String {
+        format!("{}{}", normalize_word(&self.pinyin), self.tone)
+    }
+
+    pub(super) fn question() -> String {
+        format!("Which tone did you hear? {}", style("(1, 2, 3 or 4)").dim())
+    }
+
+    pub(super) fn handle_response(&self, tone_res: &str) -> Answer {
+        self.answer(Verdict::from(tone_res.trim() == self.tone.to_string()))
+    }
+
+    /// Reveals the tone without an answer.
+    pub(super) fn skip(&self) -> Answer {
+        self.answer(Verdict::Skipped)
+    }
+
+    /// The syllable without its tone, e.g. `hao (好)`.
+    pub(super) fn hint(&self) -> String {
+        format!(
+            "Hint: {}",
+            style(format!(
+                "{} ({})",
+                normalize_word(&self.pinyin),
+                self.character
+            ))
+            .cyan()
+        )
+    }
+
+    fn answer(&self, verdict: Verdict) -> Answer {
+        let feedback = format!(
+            "{}. It was tone {}: {}, {}\n",
+            verdict.styled(),
+            style(self.tone).cyan(),
+            style(&self.pinyin).cyan(),
+            style(self.character).cyan()
+        );
+        Answer { verdict, feedback }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Voice {
     MV1,
     MV2,
@@ -250,19 +798,32 @@ pub enum Voice {
     FV1,
     FV2,
     FV3,
-    Google,
+    /// The text-to-speech engine
+    Tts,
 }
 impl Voice {
-    pub fn random() -> Self {
-        [
-            Self::MV1,
-            Self::MV2,
-            Self::MV3,
-            Self::FV1,
-            Self::FV2,
-            Self::FV3,
-        ][rand::thread_rng().gen_range(0..6)]
-        .clone()
+    /// The voices recorded in the tone archive.
+    pub const ARCHIVE: [Self; 6] = [
+        Self::MV1,
+        Self::MV2,
+        Self::MV3,
+        Self::FV1,
+        Self::FV2,
+        Self::FV3,
+    ];
+
+    pub fn random_from(voices: &[Self]) -> Self {
+        voices
+            .choose(&mut rand::thread_rng())
+            .copied()
+            .unwrap_or(Self::FV1)
+    }
+
+    /// Parses the name of a tone archive voice, e.g. `FV1`.
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ARCHIVE
+            .into_iter()
+            .find(|voice| voice.to_string().eq_ignore_ascii_case(value.trim()))
     }
 }
 
@@ -275,96 +836,88 @@ impl std::fmt::Display for Voice {
             Self::FV1 => "FV1",
             Self::FV2 => "FV2",
             Self::FV3 => "FV3",
-            Self::Google => "Google",
+            Self::Tts => "TTS",
         }
         .to_string();
         write!(f, "{str}")
     }
 }
 
+/// How long a toned recording standing in for a neutral tone is played.
+const NEUTRAL_CLIP_LENGTH: Duration = Duration::from_millis(350);
+/// Volume of a toned recording standing in for a neutral tone.
+const NEUTRAL_CLIP_VOLUME: f32 = 0.6;
+
 #[derive(Debug, Clone)]
 pub struct Pronouncation {
-    bytes: Option<Vec<Vec<Vec<u8>>>>,
-    google_bytes: Option<Vec<Vec<u8>>>,
+    clips: Vec<Clip>,
 }
 
 impl Pronouncation {
-    fn create_from_mp3(word: &mut BaseModel, voice: &Voice) -> Self {
-        let tones_str = word.tones();
-        let voice_str = voice.to_string();
-        let tones = tones_str
-            .iter()
-            .map(|phrase| {
-                phrase
-                    .split(' ')
-                    .map(|word| {
-                        get_audio_file_from_compressed_archive(&format!("{word}_{voice_str}.mp3"))
-                    })
-                    .collect::<Vec<Vec<u8>>>()
-            })
-            .collect::<Vec<Vec<Vec<u8>>>>();
+    /// No audio, used when audio is turned off.
+    pub const fn silent() -> Self {
+        Self { clips: Vec::new() }
+    }
 
-        Self {
-            bytes: Some(tones),
-            google_bytes: None,
+    /// Checks that there is an audio device to play on and something to
+    /// pronounce with: the tone archive for `tones`, otherwise the speaker.
+    pub fn check_available(speaker: &Speaker, tones: bool) -> Result<(), AudioError> {
+        if tones {
+            TONE_ARCHIVE.check()?;
+        } else {
+            speaker.check()?;
         }
+        rodio::OutputStream::try_default()?;
+        Ok(())
     }
-    fn create_from_google_translate(word: &BaseModel) -> Self {
-        // If word/phrase/sentence uses Chinese characters that do not have any tones
-        // then use Google translate to get the audio pronunciation
-        // e.g. le, ma, ge,
-        // https://translate.google.com/translate_tts?ie=UTF-8&q=了&tl=zh-TW&client=tw-ob
-
-        let urls = word.traditional
-            .iter()
-            .map(|word| {
-                format!("https://translate.google.com/translate_tts?ie=UTF-8&q={word}&tl=zh-TW&client=tw-ob")
-            })
-            .collect::<Vec<String>>();
-
-        let results = urls
-            .par_iter()
-            .map(|url| {
-                let response = reqwest::blocking::get(url).unwrap();
-                response.bytes().unwrap().to_vec()
-            })
-            .collect::<Vec<Vec<u8>>>();
 
-        Self {
-            bytes: None,
-            google_bytes: Some(results),
+    pub fn create_from_syllable(syllable: &Syllable, voice: Voice) -> Self {
+        match ArchiveBackend::syllable(&syllable.numbered(), voice) {
+            Ok(clip) => Self { clips: vec![clip] },
+            Err(e) => {
+                log::warn!("{}", e);
+                Self::silent()
+            }
         }
     }
 
-    pub fn create_from(word: &mut BaseModel, voice: &Voice) -> Self {
-        if word.has_normal_tones() {
-            return Self::create_from_google_translate(word);
+    /// Pronounces the word from the tone archive, or from the text-to-speech
+    /// engine if the archive is missing some syllables.
+    pub fn create_from(word: &mut BaseModel, voice: Voice, speaker: &Speaker) -> Self {
+        let text = word.characters().join(" ");
+        let pinyin = word.spoken_tones().join(" ");
+        let utterance = Utterance {
+            text: &text,
+            pinyin: &pinyin,
+        };
+        match speaker.speak(&utterance, voice) {
+            Ok(clips) => Self { clips },
+            Err(e) => {
+                log::warn!("{}", e);
+                Self::silent()
+            }
         }
-        Self::create_from_mp3(word, voice)
     }
 
-    pub fn play_all(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let (_stream, handle) = rodio::OutputStream::try_default()?;
-        let sink = rodio::Sink::try_new(&handle)?;
-
-        // Play mp3 files
-        if let Some(bytes) = &self.bytes {
-            for phrase in bytes {
-                for word in phrase {
-                    let cursor = Cursor::new(word.clone());
-                    let source = rodio::Decoder::new(BufReader::new(cursor))?;
-                    sink.append(source);
-                }
-            }
-            sink.sleep_until_end();
+    pub fn play_all(&self) -> Result<(), AudioError> {
+        if self.clips.is_empty() {
             return Ok(());
         }
+        let (_stream, handle) = rodio::OutputStream::try_default()?;
+        let sink = rodio::Sink::try_new(&handle)?;
 
-        // Play google translate audio
-        for phrase in self.google_bytes.as_ref().unwrap() {
-            let cursor = Cursor::new(phrase.clone());
+        for clip in &self.clips {
+            let cursor = Cursor::new(clip.bytes.clone());
             let source = rodio::Decoder::new(BufReader::new(cursor))?;
-            sink.append(source);
+            if clip.neutral {
+                sink.append(
+                    source
+                        .take_duration(NEUTRAL_CLIP_LENGTH)
+                        .amplify(NEUTRAL_CLIP_VOLUME),
+                );
+            } else {
+                sink.append(source);
+            }
         }
         sink.sleep_until_end();
         Ok(())
@@ -378,6 +931,35 @@ pub struct DictObject {
     pub(super) pinyin: Box<str>,
 }
 
+impl DictObject {
+    /// Whether the entry is a proper noun, surname or variant form and not the
+    /// usual reading of its characters.
+    pub(super) fn is_uncommon(&self) -> bool {
+        const PREFIXES: [&str; 5] = [
+            "surname",
+            "variant of",
+            "old variant",
+            "unofficial variant",
+            "used in",
+        ];
+        self.pinyin.starts_with(char::is_uppercase)
+            || PREFIXES.iter().any(|p| self.english.starts_with(p))
+    }
+
+    /// Renders the entry as e.g. `天氣 天气 tiān qì` followed by the translation.
+    pub(super) fn styled(&self) -> String {
+        let mut characters = style(&*self.traditional).bold().to_string();
+        if self.simplified != self.traditional {
+            characters = format!("{characters} {}", style(&*self.simplified).bold());
+        }
+        format!(
+            "{characters} {}\n  {}",
+            style(&*self.pinyin).cyan(),
+            self.english
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -404,6 +986,345 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sentence_pinyin() {
+        let mut model = BaseModel::new(
+            Box::new(["今天天氣很好。".into(), "你好嗎？".into()]),
+            Box::new(["今天天气很好。".into(), "你好吗？".into()]),
+            Box::new(["The weather is nice today.".into()]),
+            None,
+        );
+        assert_eq!(
+            model.pinyin(),
+            vec![
+                "jīn tiān tiān qì hěn hǎo".to_string(),
+                "nǐ hǎo ma".to_string()
+            ]
+        );
+        console::set_colors_enabled(false);
+        assert_eq!(
+            model.styled_pinyin(false),
+            "Jīntiān tiānqì hěn hǎo. Nǐhǎo ma?"
+        );
+        assert_eq!(
+            model.styled_pinyin(true),
+            "ㄐㄧㄣㄊㄧㄢ ㄊㄧㄢㄑㄧˋ ㄏㄣˇ ㄏㄠˇ。ㄋㄧˇㄏㄠˇ ˙ㄇㄚ？"
+        );
+        model.set_script(Script::Simplified);
+        assert_eq!(
+            model.sentence_pinyin("我們，去吧！", "wǒ men qù ba", false),
+            "Wǒmen, qù ba!"
+        );
+        assert_eq!(
+            model.sentence_pinyin("我們，去吧！", "wǒ men qù ba", true),
+            "ㄨㄛˇ˙ㄇㄣ，ㄑㄩˋ ˙ㄅㄚ！"
+        );
+        assert_eq!(
+            model.sentence_pinyin("我不明白。", "wǒ bù míng bai", false),
+            "Wǒ bù míngbai."
+        );
+        // Characters the dictionary has no pinyin for stay in place
+        assert_eq!(model.sentence_pinyin("㐀，走！", "zǒu", false), "㐀, zǒu!");
+
+        let mut model = BaseModel::new(
+            Box::new(["你好嗎？".into()]),
+            Box::new(["你好吗？".into()]),
+            Box::new(["How are you?".into()]),
+            None,
+        )
+        .with_pinyin(Some(Box::new(["ni3 hao3 ma".into()])));
+        assert_eq!(model.styled_pinyin(false), "Nǐhǎo ma?");
+    }
+
+    #[test]
+    fn test_umlaut() {
+        let mut model = BaseModel::new(
+            Box::new(["綠".into(), "路".into()]),
+            Box::new(["绿".into(), "路".into()]),
+            Box::new(["green".into()]),
+            None,
+        );
+        assert_eq!(model.tones(), vec!["lü4".to_string(), "lu4".to_string()]);
+        assert_eq!(model.syllables()[0].numbered(), "lü4");
+        assert_eq!(model.syllables()[0].tone, 4);
+
+        let model = BaseModel::new(
+            Box::new(["綠".into()]),
+            Box::new(["绿".into()]),
+            Box::new(["green".into()]),
+            None,
+        );
+        let answer = |answer: &str| {
+            model
+                .clone()
+                .handle_english_response(answer, options(true, false))
+                .verdict
+        };
+        for correct in ["lü4", "lv4", "lu:4", "lǜ"] {
+            assert_eq!(answer(correct), Verdict::Correct, "{correct}");
+        }
+        assert_eq!(answer("lu4"), Verdict::Wrong);
+        assert_eq!(answer("lù"), Verdict::Wrong);
+    }
+
+    #[test]
+    fn test_spoken_tones() {
+        let mut model = get_base_model();
+        assert_eq!(
+            model.spoken_tones(),
+            vec![
+                "ni2 hao3".to_string(),
+                "wo3 ai4 ni3".to_string(),
+                "ni2 hao3 le".to_string()
+            ]
+        );
+        assert_eq!(model.spoken_pinyin()[0], "ní hǎo");
+
+        console::set_colors_enabled(false);
+        let spoken = AnswerOptions {
+            spoken_tones: true,
+            ..options(false, false)
+        };
+        assert!(model
+            .question_chinese(spoken)
+            .contains("nǐ hǎo, wǒ ài nǐ, nǐ hǎo le (spoken ní hǎo, wǒ ài nǐ, ní hǎo le)"));
+        assert!(!model
+            .question_chinese(options(false, false))
+            .contains("spoken"));
+
+        let mut sentence = BaseModel::new(
+            Box::new(["你好，我很好。".into()]),
+            Box::new(["你好，我很好。".into()]),
+            Box::new(["Hello, I am fine.".into()]),
+            None,
+        );
+        assert_eq!(sentence.spoken_tones(), vec!["ni2 hao3 wo2 hen2 hao3"]);
+    }
+
+    #[test]
+    fn test_pinyin_override() {
+        let mut model = BaseModel::new(
+            Box::new(["銀行".into()]),
+            Box::new(["银行".into()]),
+            Box::new(["bank".into()]),
+            None,
+        )
+        .with_pinyin(Some(Box::new(["yin2hang2".into()])));
+        assert_eq!(model.pinyin(), vec!["yín háng".to_string()]);
+        model.set_script(Script::Simplified);
+        assert_eq!(model.tones(), vec!["yin2 hang2".to_string()]);
+
+        let model = model.with_pinyin(Some(Box::new(["yín".into(), "háng".into()])));
+        assert_eq!(model.pinyin_override, None);
+    }
+
+    #[test]
+    fn test_base_model_syllables() {
+        let mut model = BaseModel::new(
+            Box::new(["你好".into()]),
+            Box::new(["你好".into()]),
+            Box::new(["hello".into()]),
+            None,
+        );
+        let syllables = model.syllables();
+        assert_eq!(
+            syllables
+                .iter()
+                .map(|s| (s.character, s.numbered()))
+                .collect::<Vec<_>>(),
+            vec![('你', "ni3".to_string()), ('好', "hao3".to_string())]
+        );
+    }
+
+    const fn options(strict_tones: bool, require_characters: bool) -> AnswerOptions {
+        AnswerOptions {
+            strict_tones,
+            require_characters,
+            notes_on_demand: false,
+            spoken_tones: false,
+            zhuyin: false,
+            script: Script::Traditional,
+        }
+    }
+
+    #[test]
+    fn test_handle_chinese_response() {
+        let model = get_base_model();
+        let verdict = |answer: &str| {
+            model
+                .handle_chinese_response(answer, options(false, false))
+                .verdict
+        };
+        assert_eq!(verdict("Hello!"), Verdict::Correct);
+        assert_eq!(verdict("i lov you"), Verdict::Correct);
+        assert_eq!(verdict("I like you"), Verdict::Close);
+        assert_eq!(verdict("goodbye"), Verdict::Wrong);
+    }
+
+    #[test]
+    fn test_script() {
+        let mut model = get_base_model();
+        let answer = |model: &mut BaseModel, answer: &str| {
+            model
+                .handle_english_response(answer, options(false, false))
+                .verdict
+        };
+        assert!(model
+            .question_chinese(options(false, false))
+            .contains("我愛你"));
+        assert_eq!(answer(&mut model, "我爱你"), Verdict::Wrong);
+
+        model.set_script(Script::Simplified);
+        assert!(model
+            .question_chinese(options(false, false))
+            .contains("我爱你"));
+        assert_eq!(model.syllables()[2].character, '我');
+        assert_eq!(answer(&mut model, "我爱你"), Verdict::Correct);
+        assert_eq!(answer(&mut model, "我愛你"), Verdict::Wrong);
+
+        model.set_script(Script::Both);
+        assert!(model
+            .question_chinese(options(false, false))
+            .contains("我愛你 (我爱你)"));
+        assert_eq!(answer(&mut model, "我愛你"), Verdict::Correct);
+        assert_eq!(answer(&mut model, "我爱你"), Verdict::Correct);
+        assert_eq!(Script::parse(" Simplified"), Some(Script::Simplified));
+
+        let mut uneven = BaseModel::new(
+            Box::new(["我愛你".into(), "你好".into()]),
+            Box::new(["我爱你".into()]),
+            Box::new(["I love you".into(), "hello".into()]),
+            None,
+        );
+        uneven.set_script(Script::Both);
+        console::set_colors_enabled(false);
+        assert_eq!(uneven.styled_characters(), "我愛你, 你好");
+    }
+
+    #[test]
+    fn test_notes() {
+        let mut model = get_base_model();
+        let note = "This is a very common greeting.";
+        assert!(model.styled_notes().unwrap().contains(note));
+        assert!(model
+            .handle_chinese_response("hello", options(false, false))
+            .feedback
+            .contains(note));
+
+        let on_demand = AnswerOptions {
+            notes_on_demand: true,
+            ..options(false, false)
+        };
+        assert!(!model.skip(false, on_demand).feedback.contains(note));
+
+        model.notes = Some(Box::new([" ".to_string()]));
+        assert_eq!(model.styled_notes(), None);
+    }
+
+    #[test]
+    fn test_zhuyin() {
+        let mut model = get_base_model();
+        let zhuyin = AnswerOptions {
+            zhuyin: true,
+            ..options(true, false)
+        };
+        console::set_colors_enabled(false);
+        assert!(model
+            .question_chinese(zhuyin)
+            .contains("ㄋㄧˇ ㄏㄠˇ, ㄨㄛˇ ㄞˋ ㄋㄧˇ, ㄋㄧˇ ㄏㄠˇ ˙ㄌㄜ"));
+        let answer =
+            |model: &mut BaseModel, answer: &str| model.handle_english_response(answer, zhuyin);
+        assert_eq!(answer(&mut model, "ㄋㄧˇㄏㄠˇ").verdict, Verdict::Correct);
+        assert_eq!(answer(&mut model, "ni3 hao3").verdict, Verdict::Correct);
+        let wrong = answer(&mut model, "ㄋㄧˊ ㄏㄠˇ");
+        assert_eq!(wrong.verdict, Verdict::Wrong);
+        assert!(wrong.feedback.contains("Wrong tone on: ㄋㄧˇ"));
+        assert!(answer(&mut model, "ㄏˇㄠ")
+            .feedback
+            .contains("not valid zhuyin"));
+    }
+
+    #[test]
+    fn test_hint() {
+        let mut model = get_base_model();
+        assert!(model.hint(true, false).contains("h____"));
+        assert!(model
+            .hint(false, false)
+            .contains("n_ h__, w_ a_ n_, n_ h__ l_"));
+        assert!(model
+            .hint(false, true)
+            .contains("ㄋ_ ㄏ_, ㄨ_ ㄞ ㄋ_, ㄋ_ ㄏ_ ㄌ_"));
+    }
+
+    #[test]
+    fn test_handle_english_response() {
+        let mut model = get_base_model();
+        for answer in ["ni hao", "ni3hao3", "nǐ hǎo", "wo3 ai4 ni3"] {
+            assert_eq!(
+                model
+                    .handle_english_response(answer, options(false, false))
+                    .verdict,
+                Verdict::Correct
+            );
+        }
+        assert_eq!(
+            model
+                .handle_english_response("ni2 hao3", options(false, false))
+                .verdict,
+            Verdict::Correct
+        );
+        assert_eq!(
+            model
+                .handle_english_response("ni2 hao3", options(true, false))
+                .verdict,
+            Verdict::Wrong
+        );
+        assert_eq!(
+            model
+                .handle_english_response("ni hao", options(true, false))
+                .verdict,
+            Verdict::Wrong
+        );
+        assert_eq!(
+            model
+                .handle_english_response("nǐ hǎo le", options(true, false))
+                .verdict,
+            Verdict::Correct
+        );
+        assert_eq!(
+            model
+                .handle_english_response("ni men", options(false, false))
+                .verdict,
+            Verdict::Wrong
+        );
+    }
+
+    #[test]
+    fn test_handle_character_response() {
+        let mut model = get_base_model();
+        model.set_script(Script::Both);
+        for answer in ["你好", " 我爱你 ", "我愛你。"] {
+            assert_eq!(
+                model
+                    .handle_english_response(answer, options(false, true))
+                    .verdict,
+                Verdict::Correct
+            );
+        }
+        assert_eq!(
+            model
+                .handle_english_response("你們好", options(false, false))
+                .verdict,
+            Verdict::Wrong
+        );
+        assert_eq!(
+            model
+                .handle_english_response("ni hao", options(false, true))
+                .verdict,
+            Verdict::Wrong
+        );
+    }
+
     #[test]
     fn test_get_pinying() {
         let mut model = BaseModel::new(
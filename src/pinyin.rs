@@ -0,0 +1,320 @@
+use super::utils::string::mark_vowel;
+
+/// Syllables without an initial.
+const STANDALONE: [&str; 37] = [
+    "a", "o", "e", "ai", "ei", "ao", "ou", "an", "en", "ang", "eng", "er", "yi", "ya", "yo", "ye",
+    "yao", "you", "yan", "yin", "yang", "ying", "yong", "yu", "yue", "yuan", "yun", "wu", "wa",
+    "wo", "wai", "wei", "wan", "wen", "wang", "weng", "r",
+];
+
+/// Each final with the initials it is spelled after, e.g. `ju` for jü.
+const FINALS: [(&str, &str); 33] = [
+    ("a", "b p m f d t n l g k h zh ch sh z c s"),
+    ("o", "b p m f l"),
+    ("e", "m d t n l g k h zh ch sh r z c s"),
+    ("ai", "b p m d t n l g k h zh ch sh z c s"),
+    ("ei", "b p m f d t n l g k h zh sh z"),
+    ("ao", "b p m d t n l g k h zh ch sh r z c s"),
+    ("ou", "p m f d t n l g k h zh ch sh r z c s"),
+    ("an", "b p m f d t n l g k h zh ch sh r z c s"),
+    ("en", "b p m f d n g k h zh ch sh r z c s"),
+    ("ang", "b p m f d t n l g k h zh ch sh r z c s"),
+    ("eng", "b p m f d t n l g k h zh ch sh r z c s"),
+    ("ong", "d t n l g k h zh ch r z c s"),
+    ("i", "b p m d t n l j q x zh ch sh r z c s"),
+    ("ia", "d l j q x"),
+    ("ie", "b p m d t n l j q x"),
+    ("iao", "b p m d t n l j q x"),
+    ("iu", "m d n l j q x"),
+    ("ian", "b p m d t n l j q x"),
+    ("in", "b p m n l j q x"),
+    ("iang", "n l j q x"),
+    ("ing", "b p m d t n l j q x"),
+    ("iong", "j q x"),
+    ("u", "b p m f d t n l g k h zh ch sh r z c s j q x"),
+    ("ua", "g k h zh ch sh r"),
+    ("uo", "d t n l g k h zh ch sh r z c s"),
+    ("uai", "g k h zh ch sh"),
+    ("ui", "d t g k h zh ch sh r z c s"),
+    ("uan", "d t n l g k h zh ch sh r z c s j q x"),
+    ("un", "d t l g k h zh ch sh r z c s j q x"),
+    ("uang", "g k h zh ch sh"),
+    ("ue", "j q x"),
+    ("ü", "n l"),
+    ("üe", "n l"),
+];
+
+/// Letters in the longest syllables, e.g. `zhuang`.
+const MAX_SYLLABLE_LENGTH: usize = 6;
+
+/// Whether the plain letters are a Mandarin syllable, e.g. `xian` or `lü`.
+pub fn is_syllable(letters: &str) -> bool {
+    STANDALONE.contains(&letters)
+        || FINALS.iter().any(|(final_, initials)| {
+            letters
+                .strip_suffix(final_)
+                .is_some_and(|initial| initials.split(' ').any(|i| i == initial))
+        })
+}
+
+/// The plain letter of a pinyin char and the tone of its mark, with `ü` for `v`.
+fn split_char(c: char) -> Option<(char, Option<u8>)> {
+    match c {
+        'v' => Some(('ü', None)),
+        'a'..='z' | 'ü' | 'ê' => Some((c, None)),
+        _ => "aeiouü".chars().find_map(|vowel| {
+            (1..=4)
+                .find(|tone| mark_vowel(vowel, *tone) == c)
+                .map(|tone| (vowel, Some(tone)))
+        }),
+    }
+}
+
+/// Splits a syllable with a tone mark (`lǜ`) or number (`lv4`, `lu:4`) into
+/// its plain letters with `ü` and its tone, 5 for neutral.
+pub fn split_tone(syllable: &str) -> Option<(String, u8)> {
+    let syllable = syllable.trim().to_lowercase().replace("u:", "ü");
+    let letters_end = syllable.trim_end_matches(|c: char| c.is_ascii_digit());
+    let mut tone = match &syllable[letters_end.len()..] {
+        "" => None,
+        number => Some(number.parse().ok().filter(|tone| (1..=5).contains(tone))?),
+    };
+    let mut letters = String::new();
+    for c in letters_end.chars() {
+        let (letter, mark) = split_char(c)?;
+        tone = tone.or(mark);
+        letters.push(letter);
+    }
+    (!letters.is_empty()).then_some((letters, tone.unwrap_or(5)))
+}
+
+/// Puts the tone mark on the plain letters of a syllable: on `a` or `e`,
+/// on the `o` of `ou`, otherwise on the last vowel, e.g. `guì` and `liú`.
+pub fn mark(letters: &str, tone: u8) -> String {
+    let position = letters
+        .find('a')
+        .or_else(|| letters.find('e'))
+        .or_else(|| letters.find("ou"))
+        .or_else(|| letters.rfind(['i', 'o', 'u', 'ü']));
+    letters
+        .char_indices()
+        .map(|(i, c)| {
+            if Some(i) == position {
+                mark_vowel(c, tone)
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Splits run-together pinyin into syllables, e.g. `xian` into `xian` but
+/// `xi'an` into `xi` and `an`, keeping tone marks and numbers. Returns `None`
+/// if it is not pinyin.
+pub fn segment(text: &str) -> Option<Vec<String>> {
+    let text = text.to_lowercase().replace("u:", "ü");
+    let mut syllables = Vec::new();
+    let chunks = text
+        .split(|c: char| c.is_whitespace() || matches!(c, '\'' | '’' | '-'))
+        .filter(|chunk| !chunk.is_empty());
+    for chunk in chunks {
+        // A tone number ends a syllable
+        for piece in chunk.split_inclusive(|c: char| c.is_ascii_digit()) {
+            let letters = piece.trim_end_matches(|c: char| c.is_ascii_digit());
+            let chars = letters.chars().collect::<Vec<_>>();
+            let plain = chars
+                .iter()
+                .map(|c| split_char(*c).map(|(letter, _)| letter))
+                .collect::<Option<Vec<_>>>()?;
+            let lengths = split_letters(&plain, true).or_else(|| split_letters(&plain, false))?;
+            let mut start = 0;
+            for length in lengths {
+                syllables.push(chars[start..start + length].iter().collect::<String>());
+                start += length;
+            }
+            let number = &piece[letters.len()..];
+            if !number.is_empty() {
+                syllables.last_mut()?.push_str(number);
+            }
+        }
+    }
+    Some(syllables)
+}
+
+/// Lengths of the syllables of plain letters, preferring long syllables first.
+/// With `strict` no syllable but the first may start with a, o or e, as those
+/// need an apostrophe.
+fn split_letters(letters: &[char], strict: bool) -> Option<Vec<usize>> {
+    if letters.is_empty() {
+        return Some(Vec::new());
+    }
+    (1..=letters.len().min(MAX_SYLLABLE_LENGTH))
+        .rev()
+        .find_map(|length| {
+            let rest = &letters[length..];
+            if !is_syllable(&letters[..length].iter().collect::<String>())
+                || (strict && matches!(rest.first(), Some('a' | 'o' | 'e')))
+            {
+                return None;
+            }
+            let mut lengths = split_letters(rest, strict)?;
+            lengths.insert(0, length);
+            Some(lengths)
+        })
+}
+
+/// Joins syllables into a word, with an apostrophe before those starting with
+/// a, o or e, e.g. `xī'ān`.
+pub fn join<S: AsRef<str>>(syllables: &[S]) -> String {
+    let mut word = String::new();
+    for syllable in syllables {
+        let syllable = syllable.as_ref();
+        let starts_with_vowel = syllable
+            .chars()
+            .next()
+            .and_then(split_char)
+            .is_some_and(|(letter, _)| matches!(letter, 'a' | 'o' | 'e'));
+        if !word.is_empty() && starts_with_vowel {
+            word.push('\'');
+        }
+        word.push_str(syllable);
+    }
+    word
+}
+
+/// Rewrites each word of the text syllable by syllable, keeping what is not
+/// pinyin and punctuation at the end of words.
+fn convert(text: &str, syllable: impl Fn(String, u8) -> String) -> String {
+    text.split(' ')
+        .map(|word| {
+            let letters = word.trim_end_matches(|c: char| c.is_ascii_punctuation());
+            segment(letters)
+                .filter(|syllables| !syllables.is_empty())
+                .and_then(|syllables| {
+                    syllables
+                        .iter()
+                        .map(|s| split_tone(s).map(|(letters, tone)| syllable(letters, tone)))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .map_or_else(
+                    || word.to_string(),
+                    |syllables| {
+                        let mut converted = join(&syllables);
+                        if word.starts_with(char::is_uppercase) {
+                            converted = capitalize(&converted);
+                        }
+                        format!("{converted}{}", &word[letters.len()..])
+                    },
+                )
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Chinese punctuation as written in pinyin, e.g. `。` as `.`.
+pub const fn punctuation(c: char) -> char {
+    match c {
+        '。' => '.',
+        '，' | '、' => ',',
+        '？' => '?',
+        '！' => '!',
+        '：' => ':',
+        '；' => ';',
+        '「' | '」' | '『' | '』' | '“' | '”' => '"',
+        '（' => '(',
+        '）' => ')',
+        _ => c,
+    }
+}
+
+pub fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
+
+/// Converts pinyin with tone numbers to tone marks, e.g. `ni3 hao3` to
+/// `nǐ hǎo` and `lv4` to `lǜ`. Marked pinyin is left as it is.
+pub fn to_marked(text: &str) -> String {
+    convert(text, |letters, tone| mark(&letters, tone))
+}
+
+/// Converts pinyin with tone marks to tone numbers, e.g. `nǐ hǎo le` to
+/// `ni3 hao3 le`, leaving neutral syllables unnumbered.
+pub fn to_numbered(text: &str) -> String {
+    convert(text, |letters, tone| {
+        if tone == 5 {
+            letters
+        } else {
+            format!("{letters}{tone}")
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark() {
+        for (numbered, marked) in [
+            ("hao3", "hǎo"),
+            ("gei3", "gěi"),
+            ("gou3", "gǒu"),
+            ("xue2", "xué"),
+            ("liu2", "liú"),
+            ("gui4", "guì"),
+            ("zhuang4", "zhuàng"),
+            ("er4", "èr"),
+            ("nv3", "nǚ"),
+            ("lu:4", "lǜ"),
+            ("lve4", "lüè"),
+            ("ma5", "ma"),
+            ("ma", "ma"),
+        ] {
+            assert_eq!(to_marked(numbered), marked);
+        }
+        assert_eq!(split_tone("lǜ"), Some(("lü".to_string(), 4)));
+        assert_eq!(split_tone("ni6"), None);
+        assert_eq!(split_tone("3"), None);
+    }
+
+    #[test]
+    fn test_segment() {
+        let segment = |text: &str| segment(text).map(|s| s.join(" "));
+        assert_eq!(segment("xian").unwrap(), "xian");
+        assert_eq!(segment("xi'an").unwrap(), "xi an");
+        assert_eq!(segment("xī’ān").unwrap(), "xī ān");
+        assert_eq!(segment("nihao").unwrap(), "ni hao");
+        assert_eq!(segment("ni3hao3").unwrap(), "ni3 hao3");
+        assert_eq!(segment("fangan").unwrap(), "fan gan");
+        assert_eq!(segment("tiananmen").unwrap(), "tian an men");
+        assert_eq!(segment("Zhongguoren").unwrap(), "zhong guo ren");
+        assert_eq!(segment("lvse").unwrap(), "lv se");
+        assert_eq!(segment("hello"), None);
+        assert!(is_syllable("lüe"));
+        assert!(!is_syllable("lue"));
+        assert!(!is_syllable("jü"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for numbered in [
+            "ni3 hao3 le",
+            "xi1'an1",
+            "Tian1'an1men2",
+            "lü4 cha2",
+            "nü3'er2",
+            "zhong1guo2 ren2",
+            "hao3, dui4!",
+        ] {
+            let marked = to_marked(numbered);
+            assert_eq!(to_numbered(&marked), numbered, "{marked}");
+            assert_eq!(to_marked(&marked), marked);
+        }
+        assert_eq!(to_marked("xi1'an1"), "xī'ān");
+        assert_eq!(to_marked("Bei3jing1"), "Běijīng");
+        assert_eq!(to_marked("nǐ hao3, 你好"), "nǐ hǎo, 你好");
+    }
+}
@@ -0,0 +1,61 @@
+/// Turns the dictionary tones of consecutive syllables into the tones as spoken.
+///
+/// Takes each syllable's character and citation tone, 5 for neutral. Use a
+/// space for a syllable whose character is unknown.
+///
+/// - A third tone before another third tone becomes second, so a chain of
+///   third tones is spoken as second tones up to the last one (你好 ní hǎo)
+/// - 不 becomes second tone before a fourth tone (不是 bú shì)
+/// - 一 becomes second tone before a fourth tone (一樣 yí yàng) and fourth
+///   tone before the other tones (一天 yì tiān), except as an ordinal (第一)
+pub fn spoken_tones(syllables: &[(char, u8)]) -> Vec<u8> {
+    syllables
+        .iter()
+        .enumerate()
+        .map(|(i, &(character, tone))| {
+            let next = syllables.get(i + 1).map(|&(_, tone)| tone);
+            let ordinal = i > 0 && syllables[i - 1].0 == '第';
+            match (character, tone, next) {
+                ('不', 4, Some(4)) | (_, 3, Some(3)) => 2,
+                ('一', 1, Some(4 | 5)) if !ordinal => 2,
+                ('一', 1, Some(1..=3)) if !ordinal => 4,
+                _ => tone,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spoken(characters: &str, tones: &[u8]) -> Vec<u8> {
+        spoken_tones(
+            &characters
+                .chars()
+                .zip(tones.iter().copied())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_third_tone() {
+        assert_eq!(spoken("你好", &[3, 3]), [2, 3]);
+        assert_eq!(spoken("我很好", &[3, 3, 3]), [2, 2, 3]);
+        assert_eq!(spoken("很好吃", &[3, 3, 1]), [2, 3, 1]);
+        assert_eq!(spoken("好嗎", &[3, 5]), [3, 5]);
+        assert_eq!(spoken("  ", &[3, 3]), [2, 3]);
+    }
+
+    #[test]
+    fn test_bu_and_yi() {
+        assert_eq!(spoken("不是", &[4, 4]), [2, 4]);
+        assert_eq!(spoken("不好", &[4, 3]), [4, 3]);
+        assert_eq!(spoken("一樣", &[1, 4]), [2, 4]);
+        assert_eq!(spoken("一個", &[1, 5]), [2, 5]);
+        assert_eq!(spoken("一天", &[1, 1]), [4, 1]);
+        assert_eq!(spoken("一起", &[1, 3]), [4, 3]);
+        assert_eq!(spoken("第一次", &[4, 1, 4]), [4, 1, 4]);
+        assert_eq!(spoken("統一", &[3, 1]), [3, 1]);
+    }
+}
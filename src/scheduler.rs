@@ -0,0 +1,282 @@
+use super::file_io::state_dir;
+use super::models::{BaseModel, Verdict};
+use anyhow::Error;
+use rand::seq::IteratorRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+/// Items answered wrong come back after this many seconds.
+const RELEARN_DELAY: u64 = 10 * 60;
+const MIN_EASE: f64 = 1.3;
+const START_EASE: f64 = 2.5;
+
+/// How well an item was recalled, mapped to the SM-2 quality scale.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Grade {
+    Again,
+    Hard,
+    Good,
+}
+
+impl Grade {
+    const fn quality(self) -> u8 {
+        match self {
+            Self::Again => 1,
+            Self::Hard => 3,
+            Self::Good => 4,
+        }
+    }
+}
+
+impl From<Verdict> for Grade {
+    fn from(verdict: Verdict) -> Self {
+        match verdict {
+            Verdict::Correct => Self::Good,
+            Verdict::Close => Self::Hard,
+            Verdict::Wrong | Verdict::Skipped => Self::Again,
+        }
+    }
+}
+
+/// SM-2 review state of a single item.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ReviewItem {
+    ease: f64,
+    interval_days: u32,
+    repetitions: u32,
+    lapses: u32,
+    /// Unix timestamp in seconds
+    due: u64,
+}
+
+impl Default for ReviewItem {
+    fn default() -> Self {
+        Self {
+            ease: START_EASE,
+            interval_days: 0,
+            repetitions: 0,
+            lapses: 0,
+            due: 0,
+        }
+    }
+}
+
+impl ReviewItem {
+    /// Updates the state with the answer. An item asked before it is due, when
+    /// nothing else is left to ask, only counts if it was answered wrong, so
+    /// its interval does not grow early.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn review(&mut self, grade: Grade, now: u64) {
+        if self.due > now && grade != Grade::Again {
+            return;
+        }
+
+        let distance = 5.0 - f64::from(grade.quality());
+        self.ease = distance
+            .mul_add(-distance.mul_add(0.02, 0.08), self.ease + 0.1)
+            .max(MIN_EASE);
+
+        if grade == Grade::Again {
+            self.repetitions = 0;
+            self.interval_days = 0;
+            self.lapses += 1;
+            self.due = now + RELEARN_DELAY;
+            return;
+        }
+
+        self.repetitions += 1;
+        self.interval_days = match self.repetitions {
+            1 => 1,
+            2 => 6,
+            _ => (f64::from(self.interval_days) * self.ease).round() as u32,
+        };
+        self.due = now + u64::from(self.interval_days) * SECONDS_PER_DAY;
+    }
+}
+
+/// Picks the next item to ask and records how each answer went.
+///
+/// The state is keyed by [`BaseModel::key`] and saved to `review_state.json`
+/// in the state directory after every review.
+pub struct Scheduler {
+    path: PathBuf,
+    items: HashMap<String, ReviewItem>,
+}
+
+impl Scheduler {
+    pub fn load_default() -> Self {
+        Self::load(state_dir().join("review_state.json"))
+    }
+
+    pub fn load(path: PathBuf) -> Self {
+        let items = fs::read(&path).map_or_else(
+            |_| HashMap::new(),
+            |bytes| {
+                serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                    log::warn!("Could not parse review state {}: {}", path.display(), e);
+                    HashMap::new()
+                })
+            },
+        );
+        Self { path, items }
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_vec(&self.items)?)?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the index of the next item to ask, skipping the `excluded` keys.
+    ///
+    /// The most overdue item comes first, then items never seen before and
+    /// finally the item that is due soonest.
+    pub fn pick(&self, models: &[BaseModel], excluded: &[String], now: u64) -> Option<usize> {
+        let candidates = models
+            .iter()
+            .enumerate()
+            .map(|(i, model)| (i, model.key()))
+            .filter(|(_, key)| !excluded.contains(key))
+            .map(|(i, key)| (i, self.items.get(&key)))
+            .collect::<Vec<_>>();
+
+        let scheduled = candidates
+            .iter()
+            .filter_map(|(i, item)| item.map(|item| (*i, item.due)));
+
+        if let Some((i, _)) = scheduled
+            .clone()
+            .filter(|(_, due)| *due <= now)
+            .min_by_key(|(_, due)| *due)
+        {
+            return Some(i);
+        }
+
+        candidates
+            .iter()
+            .filter(|(_, item)| item.is_none())
+            .map(|(i, _)| *i)
+            .choose(&mut rand::thread_rng())
+            .or_else(|| scheduled.min_by_key(|(_, due)| *due).map(|(i, _)| i))
+    }
+
+    /// Moves the state saved under the characters of a word to its key once
+    /// a pinyin override is added, unless another word of the deck still has
+    /// that key, e.g. the `le` reading of 了 keeps the state of `了`.
+    pub fn migrate_keys(&mut self, models: &[BaseModel]) {
+        for model in models {
+            let (key, old_key) = (model.key(), model.characters_key());
+            if key == old_key
+                || self.items.contains_key(&key)
+                || models.iter().any(|other| other.key() == old_key)
+            {
+                continue;
+            }
+            if let Some(item) = self.items.remove(&old_key) {
+                self.items.insert(key, item);
+            }
+        }
+    }
+
+    pub fn review(&mut self, key: String, grade: Grade, now: u64) {
+        self.items.entry(key).or_default().review(grade, now);
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(traditional: &str) -> BaseModel {
+        BaseModel::new(
+            Box::new([traditional.into()]),
+            Box::new([traditional.into()]),
+            Box::new(["".into()]),
+            None,
+        )
+    }
+
+    fn scheduler() -> Scheduler {
+        Scheduler {
+            path: PathBuf::new(),
+            items: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_review_intervals() {
+        let mut item = ReviewItem::default();
+        item.review(Grade::Good, 0);
+        assert_eq!((item.interval_days, item.due), (1, SECONDS_PER_DAY));
+        item.review(Grade::Good, item.due);
+        assert_eq!(item.interval_days, 6);
+        item.review(Grade::Good, item.due);
+        assert_eq!(item.interval_days, 15);
+        item.review(Grade::Hard, item.due);
+        assert_eq!(item.interval_days, 35);
+        assert!(item.ease < START_EASE);
+
+        // Answered before it is due
+        let early = item.clone();
+        item.review(Grade::Good, 100);
+        assert_eq!(item, early);
+
+        item.review(Grade::Again, 100);
+        assert_eq!((item.repetitions, item.lapses), (0, 1));
+        assert_eq!(item.due, 100 + RELEARN_DELAY);
+        assert!(item.ease < START_EASE);
+
+        for _ in 0..10 {
+            item.review(Grade::Again, 0);
+        }
+        assert!((item.ease - MIN_EASE).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_pick_order() {
+        let models = [model("一"), model("二"), model("三")];
+        let mut scheduler = scheduler();
+        scheduler.review(models[0].key(), Grade::Good, 0);
+        scheduler.review(models[1].key(), Grade::Again, 0);
+
+        // 二 is due after the relearn delay, before 一
+        assert_eq!(scheduler.pick(&models, &[], RELEARN_DELAY), Some(1));
+        // Nothing is due, so the unseen item comes next
+        assert_eq!(scheduler.pick(&models, &[], 0), Some(2));
+        // Everything seen and nothing due, so the one due soonest
+        assert_eq!(scheduler.pick(&models, &[models[2].key()], 0), Some(1));
+        assert_eq!(scheduler.pick(&models[..1], &[models[0].key()], 0), None);
+    }
+
+    #[test]
+    fn test_migrate_keys() {
+        let mut scheduler = scheduler();
+        scheduler.review("了".to_string(), Grade::Good, 0);
+        scheduler.review("行".to_string(), Grade::Good, 0);
+        let models = [
+            model("了"),
+            model("了").with_pinyin(Some(Box::new(["liǎo".into()]))),
+            model("行").with_pinyin(Some(Box::new(["xíng".into()]))),
+        ];
+        scheduler.migrate_keys(&models);
+        let mut keys = scheduler.items.keys().cloned().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec!["了", "行 (xíng)"]);
+    }
+}
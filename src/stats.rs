@@ -0,0 +1,385 @@
+use super::file_io::state_dir;
+use super::models::{Answer, Verdict};
+use super::scheduler;
+use anyhow::Error;
+use console::style;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Which way a question was asked.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Direction {
+    ChineseToEnglish,
+    EnglishToChinese,
+    Tone,
+}
+
+impl std::fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            Self::ChineseToEnglish => "Chinese → English",
+            Self::EnglishToChinese => "English → Chinese",
+            Self::Tone => "Tone",
+        };
+        write!(f, "{str}")
+    }
+}
+
+/// A single answered round.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct RoundRecord {
+    /// Unix timestamp in seconds
+    pub(super) timestamp: u64,
+    pub(super) mode: Box<str>,
+    pub(super) direction: Direction,
+    pub(super) item: Box<str>,
+    pub(super) correct: bool,
+    /// Almost right, counted as not correct
+    #[serde(default)]
+    pub(super) close: bool,
+    /// Skipped without an answer, counted as not correct
+    #[serde(default)]
+    pub(super) skipped: bool,
+    pub(super) response_ms: u64,
+    /// Mode of the drill when it picked `mode` for the round, e.g. `random`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) session_mode: Option<Box<str>>,
+}
+
+impl RoundRecord {
+    pub fn new(
+        mode: &str,
+        direction: Direction,
+        item: String,
+        answer: &Answer,
+        response_time: Duration,
+    ) -> Self {
+        Self {
+            timestamp: scheduler::now(),
+            mode: mode.into(),
+            direction,
+            item: item.into(),
+            correct: answer.verdict == Verdict::Correct,
+            close: answer.verdict == Verdict::Close,
+            skipped: answer.verdict == Verdict::Skipped,
+            response_ms: u64::try_from(response_time.as_millis()).unwrap_or(u64::MAX),
+            session_mode: None,
+        }
+    }
+
+    /// Records the mode of the drill as well if it differs from the mode of the round.
+    pub fn in_session(mut self, session_mode: &str) -> Self {
+        if *self.mode != *session_mode {
+            self.session_mode = Some(session_mode.into());
+        }
+        self
+    }
+
+    /// The mode the drill was started in.
+    fn drill_mode(&self) -> &str {
+        self.session_mode.as_deref().unwrap_or(&self.mode)
+    }
+}
+
+/// All-time round history, appended to `history.jsonl` in the state directory.
+/// Rounds recorded through this instance make up the current session, those
+/// since [`History::start_drill`] the current drill.
+pub struct History {
+    path: PathBuf,
+    records: Vec<RoundRecord>,
+    session_start: usize,
+    drill_start: usize,
+}
+
+impl History {
+    pub fn load_default() -> Self {
+        Self::load(state_dir().join("history.jsonl"))
+    }
+
+    pub fn load(path: PathBuf) -> Self {
+        let records = File::open(&path).map_or_else(
+            |_| Vec::new(),
+            |file| {
+                BufReader::new(file)
+                    .lines()
+                    .map_while(Result::ok)
+                    .filter(|line| !line.trim().is_empty())
+                    .filter_map(|line| {
+                        serde_json::from_str(&line)
+                            .map_err(|e| log::warn!("Skipping invalid history line: {}", e))
+                            .ok()
+                    })
+                    .collect::<Vec<_>>()
+            },
+        );
+        let session_start = records.len();
+        Self {
+            path,
+            records,
+            session_start,
+            drill_start: session_start,
+        }
+    }
+
+    /// Starts counting the rounds of a new drill for [`History::drill_summary`].
+    pub const fn start_drill(&mut self) {
+        self.drill_start = self.records.len();
+    }
+
+    /// Adds the round to the history and appends it to the history file.
+    pub fn record(&mut self, record: RoundRecord) {
+        if let Err(e) = self.append(&record) {
+            log::warn!("Could not save history to {}: {}", self.path.display(), e);
+        }
+        self.records.push(record);
+    }
+
+    fn append(&self, record: &RoundRecord) -> Result<(), Error> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    pub fn session(&self) -> &[RoundRecord] {
+        &self.records[self.session_start..]
+    }
+
+    pub fn all(&self) -> &[RoundRecord] {
+        &self.records
+    }
+
+    /// One line summing up the rounds of the current drill.
+    pub fn drill_summary(&self) -> String {
+        let summary = Summary::of(&self.records[self.drill_start..]);
+        format!(
+            "{} {} of {} correct, {} close, {} skipped ({:.1}%)",
+            style("Session finished.").bold(),
+            summary.correct,
+            summary.rounds,
+            summary.close,
+            summary.skipped,
+            summary.accuracy()
+        )
+    }
+
+    /// Renders the session and all-time statistics.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        let _ = writeln!(
+            report,
+            "{}\n\n{:<22}{:>8}{:>9}{:>7}{:>9}{:>10}{:>11}",
+            style("Statistics").bold(),
+            "",
+            "Rounds",
+            "Correct",
+            "Close",
+            "Skipped",
+            "Accuracy",
+            "Avg. time"
+        );
+        report.push_str(&Summary::of(self.session()).row("This session"));
+        report.push_str(&Summary::of(self.all()).row("All time"));
+
+        if self.all().is_empty() {
+            return report;
+        }
+
+        let _ = writeln!(report, "\n{}", style("By mode").bold());
+        for (mode, summary) in Summary::grouped(self.all(), |r| r.drill_mode().to_string()) {
+            report.push_str(&summary.row(&mode));
+            // Random drills also list the modes their rounds were picked from
+            let picked = self
+                .all()
+                .iter()
+                .filter(|r| r.session_mode.as_deref() == Some(&mode));
+            for (picked_mode, summary) in Summary::grouped(picked, |r| r.mode.to_string()) {
+                report.push_str(&summary.row(&format!("  {picked_mode}")));
+            }
+        }
+
+        let _ = writeln!(report, "\n{}", style("By direction").bold());
+        for (direction, summary) in Summary::grouped(self.all(), |r| r.direction) {
+            report.push_str(&summary.row(&direction.to_string()));
+        }
+
+        let mut missed = Summary::grouped(self.all(), |r| r.item.to_string())
+            .into_iter()
+            .filter(|(_, summary)| summary.correct < summary.rounds)
+            .collect::<Vec<_>>();
+        missed.sort_by(|(_, a), (_, b)| {
+            (b.rounds - b.correct)
+                .cmp(&(a.rounds - a.correct))
+                .then(a.rounds.cmp(&b.rounds))
+        });
+        if !missed.is_empty() {
+            let _ = writeln!(report, "\n{}", style("Most missed").bold());
+            for (item, summary) in missed.iter().take(10) {
+                let _ = writeln!(
+                    report,
+                    "  {} {} of {} missed",
+                    style(item).cyan(),
+                    summary.rounds - summary.correct,
+                    summary.rounds
+                );
+            }
+        }
+        report
+    }
+}
+
+/// Accuracy and response time over a set of rounds.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Summary {
+    rounds: u64,
+    correct: u64,
+    close: u64,
+    skipped: u64,
+    total_ms: u64,
+}
+
+impl Summary {
+    fn add(&mut self, record: &RoundRecord) {
+        self.rounds += 1;
+        self.correct += u64::from(record.correct);
+        self.close += u64::from(record.close);
+        self.skipped += u64::from(record.skipped);
+        self.total_ms += record.response_ms;
+    }
+
+    fn of(records: &[RoundRecord]) -> Self {
+        let mut summary = Self::default();
+        for record in records {
+            summary.add(record);
+        }
+        summary
+    }
+
+    fn grouped<'a, K: Ord>(
+        records: impl IntoIterator<Item = &'a RoundRecord>,
+        key: impl Fn(&RoundRecord) -> K,
+    ) -> BTreeMap<K, Self> {
+        let mut groups = BTreeMap::<K, Self>::new();
+        for record in records {
+            groups.entry(key(record)).or_default().add(record);
+        }
+        groups
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn accuracy(&self) -> f64 {
+        if self.rounds == 0 {
+            return 0.0;
+        }
+        self.correct as f64 / self.rounds as f64 * 100.0
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn average_seconds(&self) -> f64 {
+        if self.rounds == 0 {
+            return 0.0;
+        }
+        self.total_ms as f64 / self.rounds as f64 / 1000.0
+    }
+
+    fn row(&self, label: &str) -> String {
+        format!(
+            "  {:<20}{:>8}{:>9}{:>7}{:>9}{:>9.1}%{:>10.1}s\n",
+            label,
+            self.rounds,
+            self.correct,
+            self.close,
+            self.skipped,
+            self.accuracy(),
+            self.average_seconds()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(mode: &str, direction: Direction, item: &str, correct: bool) -> RoundRecord {
+        RoundRecord {
+            timestamp: 0,
+            mode: mode.into(),
+            direction,
+            item: item.into(),
+            correct,
+            close: false,
+            skipped: false,
+            response_ms: 2000,
+            session_mode: None,
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let records = [
+            record("words", Direction::ChineseToEnglish, "好", true),
+            record("words", Direction::EnglishToChinese, "好", false),
+            record("tones", Direction::Tone, "hao3", true),
+            record("words", Direction::ChineseToEnglish, "是", true),
+        ];
+        let summary = Summary::of(&records);
+        assert_eq!((summary.rounds, summary.correct), (4, 3));
+        assert!((summary.accuracy() - 75.0).abs() < f64::EPSILON);
+        assert!((summary.average_seconds() - 2.0).abs() < f64::EPSILON);
+
+        let by_mode = Summary::grouped(&records, |r| r.mode.to_string());
+        assert_eq!(by_mode["words"].rounds, 3);
+        assert_eq!(by_mode["tones"].correct, 1);
+
+        let random = record("tones", Direction::Tone, "hao3", true).in_session("random");
+        assert_eq!(random.drill_mode(), "random");
+        assert_eq!(random.mode.as_ref(), "tones");
+        let tones = record("tones", Direction::Tone, "hao3", true).in_session("tones");
+        assert_eq!(tones.session_mode, None);
+    }
+
+    #[test]
+    fn test_history_persistence() {
+        let path =
+            std::env::temp_dir().join(format!("lingua_test_{}_history.jsonl", std::process::id()));
+        let mut history = History::load(path.clone());
+        history.record(record("words", Direction::ChineseToEnglish, "好", true));
+        history.record(record("words", Direction::EnglishToChinese, "好", false));
+        assert_eq!(history.session().len(), 2);
+        assert!(history
+            .drill_summary()
+            .contains("1 of 2 correct, 0 close, 0 skipped"));
+
+        // A new drill from the menu is summed up on its own
+        history.start_drill();
+        history.record(RoundRecord {
+            close: true,
+            ..record("words", Direction::ChineseToEnglish, "是", false)
+        });
+        history.record(RoundRecord {
+            skipped: true,
+            ..record("words", Direction::ChineseToEnglish, "是", false)
+        });
+        assert_eq!(history.session().len(), 4);
+        assert!(history
+            .drill_summary()
+            .contains("0 of 2 correct, 1 close, 1 skipped"));
+
+        let history = History::load(path.clone());
+        fs::remove_file(path).unwrap();
+        assert_eq!(history.all().len(), 4);
+        assert!(history.session().is_empty());
+        assert!(history.report().contains("Most missed"));
+        assert!(history.drill_summary().contains("0 of 0 correct"));
+    }
+}
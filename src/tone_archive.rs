@@ -0,0 +1,301 @@
+use super::error::AudioError;
+use super::file_io::{get_audio_file_from_compressed_archive, is_up_to_date};
+use anyhow::{anyhow, Error};
+use flate2::read::ZlibDecoder;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tar::Archive;
+
+pub const TONE_ARCHIVE_FILE: &str = "tone_archive.tar.zlib";
+pub const TONE_TAR_FILE: &str = "tone_archive.tar";
+pub const TONE_INDEX_FILE: &str = "tone_archive.idx";
+
+/// How many recently played clips are kept in memory.
+const CLIP_CACHE_SIZE: usize = 64;
+
+/// Recently played clips, least recently used first.
+type ClipCache = VecDeque<(Box<str>, Arc<[u8]>)>;
+
+/// Location of a single mp3 inside the uncompressed tar archive.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ArchiveEntry {
+    name: Box<str>,
+    offset: u64,
+    size: u64,
+}
+
+/// Random access to the tone clips through a sidecar offset index into the
+/// uncompressed tar archive. Falls back to scanning the compressed archive
+/// when the index is not available.
+pub struct ToneArchive {
+    archive_path: PathBuf,
+    tar_path: PathBuf,
+    index: Option<HashMap<Box<str>, ArchiveEntry>>,
+    cache: Mutex<ClipCache>,
+}
+
+impl ToneArchive {
+    /// Opens the archive through the index prebuilt in the data directory or
+    /// the one built in `cache_dir`, building the uncompressed archive and the
+    /// index there if both are missing or older than the compressed archive.
+    ///
+    /// The build is done up front by `lingua index`, otherwise when the archive
+    /// is first checked at the start of a drill.
+    pub fn load(data_dir: &Path, cache_dir: &Path) -> Self {
+        let archive_path = data_dir.join(TONE_ARCHIVE_FILE);
+        let is_fresh = |dir: &Path| {
+            is_up_to_date(&dir.join(TONE_TAR_FILE), &archive_path)
+                && is_up_to_date(&dir.join(TONE_INDEX_FILE), &archive_path)
+        };
+        let index_dir = if is_fresh(data_dir) {
+            data_dir
+        } else {
+            cache_dir
+        };
+        let (tar_path, index_path) = (
+            index_dir.join(TONE_TAR_FILE),
+            index_dir.join(TONE_INDEX_FILE),
+        );
+
+        let index = if is_fresh(index_dir) {
+            read_index(&index_path).map(Some)
+        } else if archive_path.exists() {
+            log::info!("Building the tone archive index in {}", cache_dir.display());
+            build_index(&archive_path, &tar_path, &index_path).map(Some)
+        } else {
+            Ok(None)
+        };
+
+        let index = index
+            .map_err(|e| log::warn!("Tone archive index not available: {}", e))
+            .ok()
+            .flatten();
+        Self::new(archive_path, tar_path, index)
+    }
+
+    fn new(archive_path: PathBuf, tar_path: PathBuf, index: Option<Vec<ArchiveEntry>>) -> Self {
+        Self {
+            archive_path,
+            tar_path,
+            index: index.map(|entries| {
+                entries
+                    .into_iter()
+                    .map(|entry| (entry.name.clone(), entry))
+                    .collect()
+            }),
+            cache: Mutex::new(VecDeque::with_capacity(CLIP_CACHE_SIZE)),
+        }
+    }
+
+    /// Fails if neither the index nor the compressed archive is available.
+    pub fn check(&self) -> Result<(), AudioError> {
+        if self.index.is_none() && !self.archive_path.exists() {
+            return Err(AudioError::MissingArchive(self.archive_path.clone()));
+        }
+        Ok(())
+    }
+
+    /// Returns the bytes of an mp3 clip, e.g. `hao3_FV1.mp3`.
+    pub fn get(&self, file_name: &str) -> Result<Vec<u8>, AudioError> {
+        if let Some(bytes) = self.cached(file_name) {
+            return Ok(bytes.to_vec());
+        }
+
+        let bytes = match self.index.as_ref().map(|index| index.get(file_name)) {
+            Some(Some(entry)) => self.read_entry(entry).map_err(AudioError::Archive)?,
+            Some(None) => return Err(AudioError::MissingClip(file_name.to_string())),
+            None => get_audio_file_from_compressed_archive(&self.archive_path, file_name)?,
+        };
+
+        self.insert_cache(file_name, bytes.as_slice().into());
+        Ok(bytes)
+    }
+
+    fn read_entry(&self, entry: &ArchiveEntry) -> Result<Vec<u8>, io::Error> {
+        let mut file = File::open(&self.tar_path)?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let size = usize::try_from(entry.size).map_err(io::Error::other)?;
+        let mut buffer = vec![0; size];
+        file.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn cached(&self, file_name: &str) -> Option<Arc<[u8]>> {
+        let mut cache = self.cache.lock().ok()?;
+        let position = cache.iter().position(|(name, _)| &**name == file_name)?;
+        // Move the hit to the back so the least recently used clip is evicted first
+        let hit = cache.remove(position)?;
+        let bytes = hit.1.clone();
+        cache.push_back(hit);
+        drop(cache);
+        Some(bytes)
+    }
+
+    fn insert_cache(&self, file_name: &str, bytes: Arc<[u8]>) {
+        if let Ok(mut cache) = self.cache.lock() {
+            if cache.len() >= CLIP_CACHE_SIZE {
+                cache.pop_front();
+            }
+            cache.push_back((file_name.into(), bytes));
+        }
+    }
+}
+
+fn read_index(index_path: &Path) -> Result<Vec<ArchiveEntry>, Error> {
+    Ok(serde_json::from_slice(&fs::read(index_path)?)?)
+}
+
+/// Decompresses the archive to `tar_path` and writes the offset of every
+/// entry to `index_path`.
+pub fn build_index(
+    archive_path: &Path,
+    tar_path: &Path,
+    index_path: &Path,
+) -> Result<Vec<ArchiveEntry>, Error> {
+    if let Some(parent) = tar_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut decoder = ZlibDecoder::new(File::open(archive_path)?);
+    io::copy(&mut decoder, &mut File::create(tar_path)?)?;
+
+    let mut archive = Archive::new(File::open(tar_path)?);
+    let entries = archive
+        .entries()?
+        .map(|entry| {
+            let entry = entry?;
+            let name = entry
+                .path()?
+                .to_str()
+                .ok_or_else(|| anyhow!("Invalid data: non UTF-8 file name"))?
+                .into();
+            Ok(ArchiveEntry {
+                name,
+                offset: entry.raw_file_position(),
+                size: entry.size(),
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    fs::write(index_path, serde_json::to_vec(&entries)?)?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("lingua_test_{}_{name}", std::process::id()))
+    }
+
+    fn write_archive(path: &Path, clips: &[(&str, &[u8])]) {
+        let encoder = ZlibEncoder::new(File::create(path).unwrap(), Compression::best());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, data) in clips {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder
+            .into_inner()
+            .unwrap()
+            .finish()
+            .unwrap()
+            .flush()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_indexed_archive() {
+        let (archive_path, tar_path, index_path) = (
+            temp_path("tones.tar.zlib"),
+            temp_path("tones.tar"),
+            temp_path("tones.idx"),
+        );
+
+        write_archive(
+            &archive_path,
+            &[("ni3_FV1.mp3", b"ni"), ("hao3_FV1.mp3", b"hao")],
+        );
+
+        let entries = build_index(&archive_path, &tar_path, &index_path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(read_index(&index_path).unwrap(), entries);
+
+        let archive = ToneArchive::new(archive_path.clone(), tar_path.clone(), Some(entries));
+        assert_eq!(archive.get("hao3_FV1.mp3").unwrap(), b"hao");
+        assert_eq!(archive.get("ni3_FV1.mp3").unwrap(), b"ni");
+        assert!(matches!(
+            archive.get("ma1_FV1.mp3"),
+            Err(AudioError::MissingClip(_))
+        ));
+        assert_eq!(archive.cache.lock().unwrap().len(), 2);
+        assert_eq!(&*archive.cached("hao3_FV1.mp3").unwrap(), b"hao");
+
+        let unindexed = ToneArchive::new(archive_path.clone(), tar_path.clone(), None);
+        assert_eq!(unindexed.get("ni3_FV1.mp3").unwrap(), b"ni");
+        assert!(matches!(
+            unindexed.get("ma1_FV1.mp3"),
+            Err(AudioError::MissingClip(_))
+        ));
+
+        let missing = ToneArchive::new(
+            temp_path("missing.tar.zlib"),
+            temp_path("missing.tar"),
+            None,
+        );
+        assert!(matches!(
+            missing.check(),
+            Err(AudioError::MissingArchive(_))
+        ));
+        assert!(matches!(
+            missing.get("ni3_FV1.mp3"),
+            Err(AudioError::MissingArchive(_))
+        ));
+
+        for path in [archive_path, tar_path, index_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_load_rebuilds_stale_index() {
+        let (data_dir, cache_dir) = (temp_path("data"), temp_path("cache"));
+        fs::create_dir_all(&data_dir).unwrap();
+        let archive_path = data_dir.join(TONE_ARCHIVE_FILE);
+        write_archive(&archive_path, &[("ni3_FV1.mp3", b"ni")]);
+
+        let archive = ToneArchive::load(&data_dir, &cache_dir);
+        assert_eq!(archive.get("ni3_FV1.mp3").unwrap(), b"ni");
+        assert!(!data_dir.join(TONE_INDEX_FILE).exists());
+        assert!(cache_dir.join(TONE_INDEX_FILE).exists());
+
+        // A replaced archive is newer than the index built from the old one
+        write_archive(&archive_path, &[("hao3_FV1.mp3", b"hao")]);
+        for name in [TONE_TAR_FILE, TONE_INDEX_FILE] {
+            File::options()
+                .write(true)
+                .open(cache_dir.join(name))
+                .unwrap()
+                .set_modified(std::time::UNIX_EPOCH)
+                .unwrap();
+        }
+        let archive = ToneArchive::load(&data_dir, &cache_dir);
+        assert_eq!(archive.get("hao3_FV1.mp3").unwrap(), b"hao");
+        assert!(matches!(
+            archive.get("ni3_FV1.mp3"),
+            Err(AudioError::MissingClip(_))
+        ));
+
+        fs::remove_dir_all(data_dir).unwrap();
+        fs::remove_dir_all(cache_dir).unwrap();
+    }
+}
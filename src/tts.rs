@@ -0,0 +1,546 @@
+use super::error::AudioError;
+use super::file_io::cache_dir;
+use super::models::Voice;
+use super::TONE_ARCHIVE;
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Text to pronounce, e.g. `你好` with the pinyin `ni3 hao3`.
+/// Neutral tone syllables have no tone number.
+pub struct Utterance<'a> {
+    pub text: &'a str,
+    pub pinyin: &'a str,
+}
+
+/// Tones whose recording stands in for a neutral tone syllable, most similar first.
+const NEUTRAL_STAND_IN_TONES: [u8; 4] = [4, 1, 2, 3];
+
+/// Audio of a syllable or an utterance.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Clip {
+    pub bytes: Vec<u8>,
+    /// A toned recording standing in for a neutral tone, played short and soft
+    pub neutral: bool,
+}
+
+impl Clip {
+    pub const fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            neutral: false,
+        }
+    }
+}
+
+/// A source of pronunciations.
+pub trait TtsBackend: Send + Sync {
+    /// Short name used in the cache key and in messages.
+    fn name(&self) -> &str;
+
+    /// Returns the clips to play one after another.
+    fn speak(&self, utterance: &Utterance, voice: Voice) -> Result<Vec<Clip>, AudioError>;
+}
+
+/// The recorded syllables of the tone archive.
+pub struct ArchiveBackend;
+
+impl ArchiveBackend {
+    /// The clip of a numbered syllable such as `hao3`.
+    ///
+    /// Neutral tone syllables such as `le` or `le5` use a `le5` recording if
+    /// the archive has one, and otherwise a toned recording of the syllable.
+    pub fn syllable(syllable: &str, voice: Voice) -> Result<Clip, AudioError> {
+        let candidates = Self::candidates(syllable, voice);
+        candidates
+            .iter()
+            .find_map(|(file_name, neutral)| {
+                TONE_ARCHIVE.get(file_name).ok().map(|bytes| Clip {
+                    bytes,
+                    neutral: *neutral,
+                })
+            })
+            .ok_or_else(|| AudioError::MissingClip(candidates[0].0.clone()))
+    }
+
+    /// Names of the recordings to try for a syllable, and whether each stands
+    /// in for a neutral tone. The archive spells `ü` as `v`, e.g. `lv4_FV1.mp3`.
+    fn candidates(syllable: &str, voice: Voice) -> Vec<(String, bool)> {
+        let syllable = syllable.replace('ü', "v");
+        let base = syllable.trim_end_matches(|c: char| c.is_ascii_digit());
+        match &syllable[base.len()..] {
+            "" | "5" => std::iter::once((format!("{base}5_{voice}.mp3"), false))
+                .chain(
+                    NEUTRAL_STAND_IN_TONES
+                        .iter()
+                        .map(|tone| (format!("{base}{tone}_{voice}.mp3"), true)),
+                )
+                .collect(),
+            _ => vec![(format!("{syllable}_{voice}.mp3"), false)],
+        }
+    }
+}
+
+impl TtsBackend for ArchiveBackend {
+    fn name(&self) -> &'static str {
+        "archive"
+    }
+
+    fn speak(&self, utterance: &Utterance, voice: Voice) -> Result<Vec<Clip>, AudioError> {
+        utterance
+            .pinyin
+            .split_whitespace()
+            .map(|syllable| Self::syllable(syllable, voice))
+            .collect()
+    }
+}
+
+/// An HTTP text-to-speech endpoint answering
+/// `GET {url}?ie=UTF-8&q={text}&tl={language}&client=tw-ob` with an mp3,
+/// Google Translate by default.
+pub struct HttpBackend {
+    client: reqwest::blocking::Client,
+    url: String,
+    language: String,
+}
+
+impl HttpBackend {
+    pub fn new(url: &str, language: &str) -> Self {
+        Self {
+            client: reqwest::blocking::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            url: url.to_string(),
+            language: language.to_string(),
+        }
+    }
+}
+
+impl TtsBackend for HttpBackend {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    fn speak(&self, utterance: &Utterance, _voice: Voice) -> Result<Vec<Clip>, AudioError> {
+        let response = self
+            .client
+            .get(&self.url)
+            .query(&[
+                ("ie", "UTF-8"),
+                ("q", utterance.text),
+                ("tl", &self.language),
+                ("client", "tw-ob"),
+            ])
+            .send()?
+            .error_for_status()?;
+        Ok(vec![Clip::new(response.bytes()?.to_vec())])
+    }
+}
+
+/// A local engine run once per utterance, e.g.
+/// `espeak-ng -v cmn -w {output} {text}`.
+///
+/// `{text}` is replaced with the characters, `{pinyin}` with the numbered
+/// pinyin and `{output}` with a file the engine writes the audio to.
+/// Without `{output}` the audio is read from the standard output.
+pub struct CommandBackend {
+    command: Vec<String>,
+}
+
+impl CommandBackend {
+    pub const fn new(command: Vec<String>) -> Self {
+        Self { command }
+    }
+}
+
+impl TtsBackend for CommandBackend {
+    fn name(&self) -> &str {
+        self.command.first().map_or("command", String::as_str)
+    }
+
+    fn speak(&self, utterance: &Utterance, _voice: Voice) -> Result<Vec<Clip>, AudioError> {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        let Some((program, args)) = self.command.split_first() else {
+            return Err(AudioError::Engine("No command configured".to_string()));
+        };
+        let output_path = std::env::temp_dir().join(format!(
+            "lingua_tts_{}_{}",
+            std::process::id(),
+            CALLS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let writes_file = args.iter().any(|arg| arg.contains("{output}"));
+        let args = args.iter().map(|arg| {
+            arg.replace("{text}", utterance.text)
+                .replace("{pinyin}", utterance.pinyin)
+                .replace("{output}", &output_path.to_string_lossy())
+        });
+
+        let output = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|e| AudioError::Engine(format!("Could not run {program}: {e}")))?;
+        if !output.status.success() {
+            return Err(AudioError::Engine(format!(
+                "{program} failed with {}",
+                output.status
+            )));
+        }
+
+        let bytes = if writes_file {
+            let bytes = fs::read(&output_path);
+            fs::remove_file(&output_path).ok();
+            bytes.map_err(|e| AudioError::Engine(format!("{program} wrote no audio: {e}")))?
+        } else {
+            output.stdout
+        };
+        if bytes.is_empty() {
+            return Err(AudioError::Engine(format!("{program} produced no audio")));
+        }
+        Ok(vec![Clip::new(bytes)])
+    }
+}
+
+/// Keeps the audio of another backend on disk, keyed by the backend, the voice
+/// and the text.
+pub struct CachedBackend {
+    backend: Box<dyn TtsBackend>,
+    dir: PathBuf,
+}
+
+impl CachedBackend {
+    pub fn new(backend: Box<dyn TtsBackend>, dir: PathBuf) -> Self {
+        Self { backend, dir }
+    }
+
+    fn path(&self, utterance: &Utterance, voice: Voice) -> PathBuf {
+        let key = format!("{}\n{voice}\n{}", self.backend.name(), utterance.text);
+        self.dir
+            .join(format!("{:016x}.audio", fnv1a(key.as_bytes())))
+    }
+}
+
+impl TtsBackend for CachedBackend {
+    fn name(&self) -> &str {
+        self.backend.name()
+    }
+
+    fn speak(&self, utterance: &Utterance, voice: Voice) -> Result<Vec<Clip>, AudioError> {
+        let path = self.path(utterance, voice);
+        if let Ok(bytes) = fs::read(&path) {
+            return Ok(vec![Clip::new(bytes)]);
+        }
+
+        let clips = self.backend.speak(utterance, voice)?;
+        // Only single clips are cached, mp3 clips could be joined but other formats not
+        if let [clip] = clips.as_slice() {
+            if let Err(e) =
+                fs::create_dir_all(&self.dir).and_then(|()| fs::write(&path, &clip.bytes))
+            {
+                log::warn!("Could not cache audio in {}: {}", path.display(), e);
+            }
+        }
+        Ok(clips)
+    }
+}
+
+/// 64-bit FNV-1a, stable across Rust versions unlike the std hasher.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Engine used for what the tone archive has no recordings of.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    #[default]
+    Http,
+    Command,
+    None,
+}
+
+impl Engine {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "http" => Some(Self::Http),
+            "command" => Some(Self::Command),
+            "none" | "off" => Some(Self::None),
+            _ => None,
+        }
+    }
+}
+
+/// Text-to-speech settings, see [`Speaker`].
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TtsConfig {
+    pub engine: Engine,
+    /// Endpoint of the `http` engine
+    pub url: String,
+    /// Language requested from the `http` engine
+    pub language: String,
+    /// Program and arguments of the `command` engine
+    pub command: Vec<String>,
+    /// Keep the audio of the engine on disk
+    pub cache: bool,
+}
+
+impl Default for TtsConfig {
+    fn default() -> Self {
+        Self {
+            engine: Engine::default(),
+            url: "https://translate.google.com/translate_tts".to_string(),
+            language: "zh-TW".to_string(),
+            command: Vec::new(),
+            cache: true,
+        }
+    }
+}
+
+impl TtsConfig {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.engine == Engine::Http && !self.url.starts_with("http") {
+            return Err(anyhow!("tts.url must be an http or https URL"));
+        }
+        if self.engine == Engine::Command && self.command.is_empty() {
+            return Err(anyhow!("tts.command must be set for the command engine"));
+        }
+        Ok(())
+    }
+}
+
+/// Pronounces from the tone archive, falling back to the configured engine
+/// for whatever the archive has no recordings of.
+pub struct Speaker {
+    archive: ArchiveBackend,
+    engine: Option<Box<dyn TtsBackend>>,
+}
+
+impl Speaker {
+    pub fn new(config: &TtsConfig) -> Self {
+        let engine: Option<Box<dyn TtsBackend>> = match config.engine {
+            Engine::Http => Some(Box::new(HttpBackend::new(&config.url, &config.language))),
+            Engine::Command => Some(Box::new(CommandBackend::new(config.command.clone()))),
+            Engine::None => None,
+        };
+        let engine = engine.map(|engine| -> Box<dyn TtsBackend> {
+            if config.cache {
+                Box::new(CachedBackend::new(engine, cache_dir().join("audio")))
+            } else {
+                engine
+            }
+        });
+        Self {
+            archive: ArchiveBackend,
+            engine,
+        }
+    }
+
+    /// Fails if there is neither a tone archive nor an engine to fall back to.
+    pub fn check(&self) -> Result<(), AudioError> {
+        if self.engine.is_some() {
+            Ok(())
+        } else {
+            TONE_ARCHIVE.check()
+        }
+    }
+
+    pub fn speak(&self, utterance: &Utterance, voice: Voice) -> Result<Vec<Clip>, AudioError> {
+        match (self.archive.speak(utterance, voice), &self.engine) {
+            (Ok(clips), _) => Ok(clips),
+            (Err(_), Some(engine)) => engine.speak(utterance, Voice::Tts),
+            (Err(e), None) => Err(e),
+        }
+    }
+}
+
+impl std::fmt::Debug for Speaker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Speaker")
+            .field("archive", &self.archive.name())
+            .field("engine", &self.engine.as_ref().map(|engine| engine.name()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    struct CountingBackend(Arc<AtomicUsize>);
+
+    impl TtsBackend for CountingBackend {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        fn speak(&self, utterance: &Utterance, voice: Voice) -> Result<Vec<Clip>, AudioError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(vec![Clip::new(
+                format!("{voice} {}", utterance.text).into_bytes(),
+            )])
+        }
+    }
+
+    #[test]
+    fn test_cached_backend() {
+        let dir = std::env::temp_dir().join(format!("lingua_test_{}_tts", std::process::id()));
+        let counter = Arc::new(AtomicUsize::new(0));
+        let cached = CachedBackend::new(Box::new(CountingBackend(counter.clone())), dir.clone());
+        let hello = Utterance {
+            text: "你好",
+            pinyin: "ni3 hao3",
+        };
+
+        assert_eq!(
+            cached.speak(&hello, Voice::FV1).unwrap(),
+            vec![Clip::new("FV1 你好".as_bytes().to_vec())]
+        );
+        // The second request is answered from the cache
+        assert_eq!(
+            cached.speak(&hello, Voice::FV1).unwrap(),
+            vec![Clip::new("FV1 你好".as_bytes().to_vec())]
+        );
+        assert_eq!(
+            cached.speak(&hello, Voice::MV1).unwrap(),
+            vec![Clip::new("MV1 你好".as_bytes().to_vec())]
+        );
+        assert_ne!(
+            cached.path(&hello, Voice::FV1),
+            cached.path(&hello, Voice::MV1)
+        );
+        assert_eq!(counter.load(Ordering::Relaxed), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_backend() {
+        let hello = Utterance {
+            text: "你好",
+            pinyin: "ni3 hao3",
+        };
+        let stdout = CommandBackend::new(vec!["printf".into(), "%s".into(), "{pinyin}".into()]);
+        assert_eq!(
+            stdout.speak(&hello, Voice::Tts).unwrap(),
+            vec![Clip::new(b"ni3 hao3".to_vec())]
+        );
+
+        let file = CommandBackend::new(
+            [
+                "sh",
+                "-c",
+                "printf %s \"$1\" > \"$0\"",
+                "{output}",
+                "{text}",
+            ]
+            .map(String::from)
+            .to_vec(),
+        );
+        assert_eq!(
+            file.speak(&hello, Voice::Tts).unwrap(),
+            vec![Clip::new("你好".as_bytes().to_vec())]
+        );
+
+        let failing = CommandBackend::new(vec!["false".into()]);
+        assert!(matches!(
+            failing.speak(&hello, Voice::Tts),
+            Err(AudioError::Engine(_))
+        ));
+        let missing = CommandBackend::new(vec!["lingua-no-such-engine".into()]);
+        assert!(matches!(
+            missing.speak(&hello, Voice::Tts),
+            Err(AudioError::Engine(_))
+        ));
+    }
+
+    #[test]
+    fn test_http_backend() {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/tts", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request_line = String::new();
+            BufReader::new(&stream)
+                .read_line(&mut request_line)
+                .unwrap();
+            let body = request_line.trim();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        });
+
+        let hello = Utterance {
+            text: "你好",
+            pinyin: "ni3 hao3",
+        };
+        let clips = HttpBackend::new(&url, "zh-CN")
+            .speak(&hello, Voice::Tts)
+            .unwrap();
+        server.join().unwrap();
+        assert_eq!(
+            String::from_utf8(clips[0].bytes.clone()).unwrap(),
+            "GET /tts?ie=UTF-8&q=%E4%BD%A0%E5%A5%BD&tl=zh-CN&client=tw-ob HTTP/1.1"
+        );
+    }
+
+    #[test]
+    fn test_archive_candidates() {
+        assert_eq!(
+            ArchiveBackend::candidates("hao3", Voice::FV1),
+            vec![("hao3_FV1.mp3".to_string(), false)]
+        );
+        let neutral = [
+            ("le5_MV2.mp3", false),
+            ("le4_MV2.mp3", true),
+            ("le1_MV2.mp3", true),
+            ("le2_MV2.mp3", true),
+            ("le3_MV2.mp3", true),
+        ]
+        .map(|(name, neutral)| (name.to_string(), neutral))
+        .to_vec();
+        assert_eq!(ArchiveBackend::candidates("le", Voice::MV2), neutral);
+        assert_eq!(ArchiveBackend::candidates("le5", Voice::MV2), neutral);
+        assert_eq!(
+            ArchiveBackend::candidates("lü4", Voice::FV1),
+            vec![("lv4_FV1.mp3".to_string(), false)]
+        );
+    }
+
+    #[test]
+    fn test_config_validate() {
+        assert!(TtsConfig::default().validate().is_ok());
+        let config = TtsConfig {
+            engine: Engine::Command,
+            ..TtsConfig::default()
+        };
+        assert!(config.validate().is_err());
+        assert_eq!(Engine::parse(" Command"), Some(Engine::Command));
+        assert_eq!(Engine::parse("google"), None);
+    }
+
+    #[test]
+    fn test_speaker_check() {
+        // An engine can speak without the tone archive
+        let config = TtsConfig {
+            cache: false,
+            ..TtsConfig::default()
+        };
+        assert!(Speaker::new(&config).check().is_ok());
+    }
+}
@@ -1,6 +1,11 @@
-use super::{game::Mode, models::BaseModel};
-use console::Term;
+use super::{
+    game::Mode,
+    models::{BaseModel, Script},
+    scheduler,
+};
+use console::{style, Term};
 use rand::random;
+use std::sync::Mutex;
 use std::{fmt::Display, io::Write};
 
 // Step 1: Define the trait
@@ -26,40 +31,360 @@ impl StyledWrite for Term {
     }
 }
 
-pub fn get_random_base_model(mode: &Mode, load_attributes: bool) -> BaseModel {
+pub fn get_random_base_model(mode: &Mode, script: Script) -> BaseModel {
     let file = mode.get_json_file();
     let mut base_model = file[random::<usize>() % file.len()].clone();
-    if load_attributes {
-        base_model.pinyin();
-    }
+    base_model.set_script(script);
+    base_model.pinyin();
+    base_model
+}
+
+/// Picks the next base model due for review, falling back to a random one.
+pub fn get_scheduled_base_model(
+    mode: &Mode,
+    scheduler: &Mutex<scheduler::Scheduler>,
+    excluded: &[String],
+    script: Script,
+) -> BaseModel {
+    let file = mode.get_json_file();
+    let index = scheduler
+        .lock()
+        .ok()
+        .and_then(|scheduler| scheduler.pick(file, excluded, scheduler::now()))
+        .unwrap_or_else(|| random::<usize>() % file.len());
+    let mut base_model = file[index].clone();
+    base_model.set_script(script);
+    base_model.pinyin();
     base_model
 }
 
+/// Renders the simple markup used in notes: `**bold**`, `*italic*` and `` `pinyin` ``.
+/// Markers without a closing pair are kept as they are.
+pub fn render_markup(text: &str) -> String {
+    let mut rendered = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(['*', '`']) {
+        rendered.push_str(&rest[..start]);
+        let marker = if rest[start..].starts_with("**") {
+            "**"
+        } else {
+            &rest[start..=start]
+        };
+        let after = &rest[start + marker.len()..];
+        let closing = after.find(marker).filter(|end| {
+            let inner = &after[..*end];
+            !inner.is_empty()
+                && !inner.starts_with(char::is_whitespace)
+                && !inner.ends_with(char::is_whitespace)
+        });
+        if let Some(end) = closing {
+            let inner = style(&after[..end]);
+            let inner = match marker {
+                "**" => inner.bold(),
+                "*" => inner.italic(),
+                _ => inner.cyan(),
+            };
+            rendered.push_str(&inner.to_string());
+            rest = &after[end + marker.len()..];
+        } else {
+            rendered.push_str(marker);
+            rest = after;
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
 pub mod string {
+    use super::super::pinyin;
+
     pub const fn match_tone(c: char) -> u8 {
         match c {
-            'ā' | 'ē' | 'ī' | 'ō' | 'ū' => 1,
-            'á' | 'é' | 'í' | 'ó' | 'ú' => 2,
-            'ǎ' | 'ě' | 'ǐ' | 'ǒ' | 'ǔ' => 3,
-            'à' | 'è' | 'ì' | 'ò' | 'ù' => 4,
+            'ā' | 'ē' | 'ī' | 'ō' | 'ū' | 'ǖ' => 1,
+            'á' | 'é' | 'í' | 'ó' | 'ú' | 'ǘ' => 2,
+            'ǎ' | 'ě' | 'ǐ' | 'ǒ' | 'ǔ' | 'ǚ' => 3,
+            'à' | 'è' | 'ì' | 'ò' | 'ù' | 'ǜ' => 4,
             _ => 5,
         }
     }
 
+    /// Removes the tone mark, keeping `ü` apart from `u` so that e.g. lǜ and
+    /// lù stay different.
     pub const fn normalize_char(c: char) -> char {
         match c {
             'ā' | 'á' | 'ǎ' | 'à' => 'a',
             'ē' | 'é' | 'ě' | 'è' => 'e',
             'ī' | 'í' | 'ǐ' | 'ì' => 'i',
             'ō' | 'ó' | 'ǒ' | 'ò' => 'o',
-            'ū' | 'ú' | 'ǔ' | 'ù' | 'ǖ' | 'ǘ' | 'ǚ' | 'ǜ' | 'ü' => 'u',
+            'ū' | 'ú' | 'ǔ' | 'ù' => 'u',
+            'ǖ' | 'ǘ' | 'ǚ' | 'ǜ' => 'ü',
             _ => c,
         }
     }
 
+    /// Puts the mark of `tone` on a plain vowel, e.g. `a` and 3 is `ǎ`.
+    pub const fn mark_vowel(vowel: char, tone: u8) -> char {
+        const MARKED: [(char, [char; 4]); 6] = [
+            ('a', ['ā', 'á', 'ǎ', 'à']),
+            ('e', ['ē', 'é', 'ě', 'è']),
+            ('i', ['ī', 'í', 'ǐ', 'ì']),
+            ('o', ['ō', 'ó', 'ǒ', 'ò']),
+            ('u', ['ū', 'ú', 'ǔ', 'ù']),
+            ('ü', ['ǖ', 'ǘ', 'ǚ', 'ǜ']),
+        ];
+        let mut i = 0;
+        while i < MARKED.len() {
+            if MARKED[i].0 == vowel && matches!(tone, 1..=4) {
+                return MARKED[i].1[tone as usize - 1];
+            }
+            i += 1;
+        }
+        vowel
+    }
+
+    /// Moves a marked pinyin syllable to another tone, e.g. `nǐ` to `ní`.
+    /// Neutral syllables are left as they are.
+    pub fn retone(syllable: &str, tone: u8) -> String {
+        match pinyin::split_tone(syllable) {
+            Some((letters, 1..=4)) => pinyin::mark(&letters, tone),
+            _ => syllable.to_string(),
+        }
+    }
+
     pub fn normalize_word<S: AsRef<str>>(pinyin: S) -> String {
         pinyin.as_ref().chars().map(normalize_char).collect()
     }
+
+    /// Whether the char is a CJK ideograph.
+    pub const fn is_hanzi(c: char) -> bool {
+        matches!(c,
+            '\u{4E00}'..='\u{9FFF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{20000}'..='\u{2EBEF}'
+        )
+    }
+
+    /// Removes spaces and punctuation, keeping only the Chinese characters.
+    pub fn strip_non_hanzi<S: AsRef<str>>(text: S) -> String {
+        text.as_ref().chars().filter(|c| is_hanzi(*c)).collect()
+    }
+
+    /// Keeps the first letter of each word and replaces the others with `_`.
+    pub fn mask_letters(text: &str) -> String {
+        let mut previous_is_letter = false;
+        text.chars()
+            .map(|c| {
+                let masked = if c.is_alphanumeric() && previous_is_letter {
+                    '_'
+                } else {
+                    c
+                };
+                previous_is_letter = c.is_alphanumeric();
+                masked
+            })
+            .collect()
+    }
+
+    /// Phrases that are accepted in place of each other, compared after normalization.
+    const SYNONYMS: &[(&str, &str)] = &[
+        ("thanks", "thank you"),
+        ("thx", "thank you"),
+        ("ok", "okay"),
+        ("hi", "hello"),
+        ("bye", "goodbye"),
+        ("good bye", "goodbye"),
+        ("can not", "cannot"),
+    ];
+
+    /// Normalizes an English answer: lowercases, expands contractions, drops
+    /// punctuation, articles and a leading infinitive "to".
+    pub fn normalize_english<S: AsRef<str>>(text: S) -> String {
+        let text = text
+            .as_ref()
+            .to_lowercase()
+            .replace(['’', '`'], "'")
+            .replace("can't", "cannot")
+            .replace("won't", "will not")
+            .replace("shan't", "shall not")
+            .replace("n't", " not")
+            .replace("'m", " am")
+            .replace("'re", " are")
+            .replace("'ll", " will")
+            .replace("'ve", " have")
+            .replace("'d", " would");
+        let cleaned = text
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '\'' {
+                    c
+                } else {
+                    ' '
+                }
+            })
+            .collect::<String>()
+            .replace('\'', "");
+
+        let mut words = cleaned
+            .split_whitespace()
+            .filter(|w| !matches!(*w, "a" | "an" | "the"))
+            .collect::<Vec<_>>();
+        if words.len() > 1 && words[0] == "to" {
+            words.remove(0);
+        }
+
+        let mut normalized = format!(" {} ", words.join(" "));
+        for (from, to) in SYNONYMS {
+            normalized = normalized.replace(&format!(" {from} "), &format!(" {to} "));
+        }
+        normalized.trim().to_string()
+    }
+
+    /// Levenshtein distance between two sequences.
+    pub fn edit_distance<T: PartialEq>(a: &[T], b: &[T]) -> usize {
+        let mut previous = (0..=b.len()).collect::<Vec<_>>();
+        let mut current = vec![0; b.len() + 1];
+        for (i, x) in a.iter().enumerate() {
+            current[0] = i + 1;
+            for (j, y) in b.iter().enumerate() {
+                let substitution = previous[j] + usize::from(x != y);
+                current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+            }
+            std::mem::swap(&mut previous, &mut current);
+        }
+        previous[b.len()]
+    }
+
+    /// How close an English answer is to the expected translation.
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    pub enum EnglishMatch {
+        None,
+        /// A word is missing or wrong, or there are several typos
+        Close,
+        /// Matches after normalization allowing for a small typo in each word
+        Exact,
+    }
+
+    pub fn compare_english(expected: &str, answer: &str) -> EnglishMatch {
+        let expected = normalize_english(expected);
+        let answer = normalize_english(answer);
+        if answer.is_empty() {
+            return EnglishMatch::None;
+        }
+
+        let expected_words = expected.split_whitespace().collect::<Vec<_>>();
+        let answer_words = answer.split_whitespace().collect::<Vec<_>>();
+        let chars = |word: &str| word.chars().collect::<Vec<_>>();
+        // Each word may have a typo, scaled by its length
+        let is_typo = |expected: &str, answer: &str| {
+            let tolerance = match expected.chars().count() {
+                0..=3 => 0,
+                4..=7 => 1,
+                _ => 2,
+            };
+            edit_distance(&chars(expected), &chars(answer)) <= tolerance
+        };
+        if expected_words.len() == answer_words.len()
+            && expected_words
+                .iter()
+                .zip(&answer_words)
+                .all(|(expected, answer)| is_typo(expected, answer))
+        {
+            return EnglishMatch::Exact;
+        }
+
+        let expected_len = expected.chars().count();
+        let char_distance = edit_distance(&chars(&expected), &chars(&answer));
+        let word_distance = edit_distance(&expected_words, &answer_words);
+        if (expected_words.len() >= 3 && word_distance <= 1)
+            || (expected_len >= 4 && char_distance * 4 <= expected_len)
+        {
+            return EnglishMatch::Close;
+        }
+        EnglishMatch::None
+    }
+
+    /// Result of comparing a pinyin answer against the expected syllables.
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub struct ToneCheck {
+        /// The letters match when tones, spaces and apostrophes are ignored
+        pub letters_match: bool,
+        /// The answer gave a tone for at least one syllable
+        pub has_tones: bool,
+        /// Indexes of the expected syllables that were given the wrong tone
+        pub wrong_tones: Vec<usize>,
+    }
+
+    /// Compares an answer written with tone numbers (`ni3 hao3`), tone marks
+    /// (`nǐhǎo`) or without tones (`ni hao`) against numbered syllables
+    /// such as `ni3 hao3 le`.
+    ///
+    /// `ü` may also be typed as `v` or `u:`, but not as `u`.
+    ///
+    /// The answer is aligned to the expected syllables by letter position, so
+    /// run-together answers do not need to be segmented. Syllables without a
+    /// tone count as neutral when the answer has tones at all.
+    pub fn compare_pinyin(expected: &str, answer: &str) -> ToneCheck {
+        let expected = expected
+            .split_whitespace()
+            .map(|syllable| {
+                let letters = syllable.trim_end_matches(|c: char| c.is_ascii_digit());
+                let tone = syllable[letters.len()..].parse::<u8>().unwrap_or(5);
+                (letters, tone)
+            })
+            .collect::<Vec<_>>();
+
+        let mut ends = Vec::with_capacity(expected.len());
+        let mut total = 0;
+        for (letters, _) in &expected {
+            total += letters.chars().count();
+            ends.push(total);
+        }
+        let syllable_at = |letter: usize| ends.iter().position(|end| letter < *end);
+
+        let mut letters = String::new();
+        let mut letter_count: usize = 0;
+        let mut tones: Vec<Option<u8>> = vec![None; expected.len()];
+        let answer = answer
+            .trim()
+            .to_lowercase()
+            .replace("u:", "ü")
+            .replace('v', "ü");
+        for c in answer.chars() {
+            if let Some(tone) = c.to_digit(10).filter(|d| (1..=5).contains(d)) {
+                if let Some(syllable) = letter_count.checked_sub(1).and_then(syllable_at) {
+                    tones[syllable] = u8::try_from(tone).ok();
+                }
+                continue;
+            }
+            let normalized = normalize_char(c);
+            if !normalized.is_alphabetic() {
+                continue;
+            }
+            if match_tone(c) != 5 {
+                if let Some(syllable) = syllable_at(letter_count) {
+                    tones[syllable] = Some(match_tone(c));
+                }
+            }
+            letters.push(normalized);
+            letter_count += 1;
+        }
+
+        let has_tones = tones.iter().any(Option::is_some);
+        let wrong_tones = expected
+            .iter()
+            .zip(&tones)
+            .enumerate()
+            .filter(|(_, ((_, expected), given))| given.unwrap_or(5) != *expected)
+            .map(|(i, _)| i)
+            .collect();
+
+        ToneCheck {
+            letters_match: letters == expected.iter().map(|(l, _)| *l).collect::<String>(),
+            has_tones,
+            wrong_tones,
+        }
+    }
 }
 
 // tests
@@ -75,6 +400,7 @@ mod tests {
         assert_eq!(string::match_tone('ǎ'), 3);
         assert_eq!(string::match_tone('à'), 4);
         assert_eq!(string::match_tone('a'), 5);
+        assert_eq!(string::match_tone('ǜ'), 4);
     }
 
     #[test]
@@ -84,10 +410,120 @@ mod tests {
         assert_eq!(string::normalize_char('ǎ'), 'a');
         assert_eq!(string::normalize_char('à'), 'a');
         assert_eq!(string::normalize_char('a'), 'a');
+        assert_eq!(string::normalize_char('ǚ'), 'ü');
+        assert_eq!(string::normalize_char('ǔ'), 'u');
+    }
+
+    #[test]
+    fn test_retone() {
+        assert_eq!(string::mark_vowel('a', 3), 'ǎ');
+        assert_eq!(string::mark_vowel('ü', 4), 'ǜ');
+        assert_eq!(string::mark_vowel('a', 5), 'a');
+        assert_eq!(string::retone("nǐ", 2), "ní");
+        assert_eq!(string::retone("yī", 4), "yì");
+        assert_eq!(string::retone("ma", 2), "ma");
+        assert_eq!(string::retone("lǚ", 4), "lǜ");
     }
 
     #[test]
     fn test_normalize_word() {
         assert_eq!(string::normalize_word("āáǎàa"), "aaaaa");
     }
+
+    #[test]
+    fn test_render_markup() {
+        console::set_colors_enabled(false);
+        assert_eq!(
+            render_markup("Use **了** after `hǎo`, *e.g.* 好了"),
+            "Use 了 after hǎo, e.g. 好了"
+        );
+        assert_eq!(
+            render_markup("2 * 3 = 6 and ** or ``"),
+            "2 * 3 = 6 and ** or ``"
+        );
+    }
+
+    #[test]
+    fn test_mask_letters() {
+        assert_eq!(string::mask_letters("hello"), "h____");
+        assert_eq!(string::mask_letters("I love you!"), "I l___ y__!");
+    }
+
+    #[test]
+    fn test_normalize_english() {
+        assert_eq!(
+            string::normalize_english("The weather is very nice today."),
+            "weather is very nice today"
+        );
+        assert_eq!(string::normalize_english("To eat"), "eat");
+        assert_eq!(string::normalize_english("Thanks!"), "thank you");
+        assert_eq!(string::normalize_english("I'm fine"), "i am fine");
+        assert_eq!(string::normalize_english("I can't"), "i cannot");
+        assert_eq!(string::normalize_english("I can not"), "i cannot");
+        assert_eq!(string::normalize_english("I won’t go"), "i will not go");
+        assert_eq!(string::normalize_english("Don't"), "do not");
+    }
+
+    #[test]
+    fn test_compare_english() {
+        use string::{compare_english, EnglishMatch};
+        assert_eq!(compare_english("thank you", "thanks"), EnglishMatch::Exact);
+        assert_eq!(compare_english("to eat", "eat"), EnglishMatch::Exact);
+        assert_eq!(compare_english("hello", "helo"), EnglishMatch::Exact);
+        assert_eq!(
+            compare_english(
+                "The weather is very nice today.",
+                "the weather is nice today"
+            ),
+            EnglishMatch::Close
+        );
+        assert_eq!(compare_english("is", "it"), EnglishMatch::None);
+        assert_eq!(compare_english("okay", "finished"), EnglishMatch::None);
+        assert_eq!(compare_english("hello", ""), EnglishMatch::None);
+    }
+
+    #[test]
+    fn test_strip_non_hanzi() {
+        assert!(string::is_hanzi('好'));
+        assert!(!string::is_hanzi('。'));
+        assert_eq!(string::strip_non_hanzi(" 你好嗎？ "), "你好嗎");
+    }
+
+    #[test]
+    fn test_compare_pinyin() {
+        let correct = string::ToneCheck {
+            letters_match: true,
+            has_tones: true,
+            wrong_tones: vec![],
+        };
+        for answer in [
+            "ni3 hao3 le",
+            "ni3hao3le5",
+            "nǐ hǎo le",
+            "nǐhǎole",
+            "Nǐ hǎo le.",
+        ] {
+            assert_eq!(string::compare_pinyin("ni3 hao3 le", answer), correct);
+        }
+
+        let toneless = string::compare_pinyin("ni3 hao3", "ni hao");
+        assert!(toneless.letters_match && !toneless.has_tones);
+
+        let wrong = string::compare_pinyin("ni3 hao3", "ni2hao3");
+        assert!(wrong.letters_match);
+        assert_eq!(wrong.wrong_tones, vec![0]);
+
+        // A tone is missing so the syllable is taken as neutral
+        assert_eq!(
+            string::compare_pinyin("ni3 hao3", "nǐ hao").wrong_tones,
+            vec![1]
+        );
+        assert!(!string::compare_pinyin("ni3 hao3", "ni3 ma1").letters_match);
+
+        for answer in ["lü4 cha2", "lv4cha2", "lu:4 cha2", "lǜ chá"] {
+            assert_eq!(string::compare_pinyin("lü4 cha2", answer), correct);
+        }
+        assert!(!string::compare_pinyin("lü4 cha2", "lu4 cha2").letters_match);
+        assert!(!string::compare_pinyin("lu4", "lü4").letters_match);
+    }
 }
@@ -0,0 +1,342 @@
+use super::pinyin::split_tone;
+
+/// Zhuyin (Bopomofo) of the pinyin initials.
+const INITIALS: [(&str, char); 21] = [
+    ("b", 'ㄅ'),
+    ("p", 'ㄆ'),
+    ("m", 'ㄇ'),
+    ("f", 'ㄈ'),
+    ("d", 'ㄉ'),
+    ("t", 'ㄊ'),
+    ("n", 'ㄋ'),
+    ("l", 'ㄌ'),
+    ("g", 'ㄍ'),
+    ("k", 'ㄎ'),
+    ("h", 'ㄏ'),
+    ("j", 'ㄐ'),
+    ("q", 'ㄑ'),
+    ("x", 'ㄒ'),
+    ("zh", 'ㄓ'),
+    ("ch", 'ㄔ'),
+    ("sh", 'ㄕ'),
+    ("r", 'ㄖ'),
+    ("z", 'ㄗ'),
+    ("c", 'ㄘ'),
+    ("s", 'ㄙ'),
+];
+
+/// Zhuyin of the pinyin finals as written after an initial. Where two finals
+/// share a spelling the first is used when converting back.
+const FINALS: [(&str, &str); 39] = [
+    ("a", "ㄚ"),
+    ("o", "ㄛ"),
+    ("e", "ㄜ"),
+    ("ê", "ㄝ"),
+    ("ai", "ㄞ"),
+    ("ei", "ㄟ"),
+    ("ao", "ㄠ"),
+    ("ou", "ㄡ"),
+    ("an", "ㄢ"),
+    ("en", "ㄣ"),
+    ("ang", "ㄤ"),
+    ("eng", "ㄥ"),
+    ("ong", "ㄨㄥ"),
+    ("er", "ㄦ"),
+    ("i", "ㄧ"),
+    ("ia", "ㄧㄚ"),
+    ("io", "ㄧㄛ"),
+    ("ie", "ㄧㄝ"),
+    ("iao", "ㄧㄠ"),
+    ("iu", "ㄧㄡ"),
+    ("ian", "ㄧㄢ"),
+    ("in", "ㄧㄣ"),
+    ("iang", "ㄧㄤ"),
+    ("ing", "ㄧㄥ"),
+    ("iong", "ㄩㄥ"),
+    ("u", "ㄨ"),
+    ("ua", "ㄨㄚ"),
+    ("uo", "ㄨㄛ"),
+    ("uai", "ㄨㄞ"),
+    ("ui", "ㄨㄟ"),
+    ("uan", "ㄨㄢ"),
+    ("un", "ㄨㄣ"),
+    ("uang", "ㄨㄤ"),
+    ("ueng", "ㄨㄥ"),
+    ("ü", "ㄩ"),
+    ("üe", "ㄩㄝ"),
+    ("üan", "ㄩㄢ"),
+    ("ün", "ㄩㄣ"),
+    ("r", "ㄦ"),
+];
+
+/// Spellings of the finals without an initial, e.g. `you` for `iu`.
+const ZERO_INITIAL: [(&str, &str); 26] = [
+    ("yi", "i"),
+    ("ya", "ia"),
+    ("yo", "io"),
+    ("ye", "ie"),
+    ("yao", "iao"),
+    ("you", "iu"),
+    ("yan", "ian"),
+    ("yin", "in"),
+    ("yang", "iang"),
+    ("ying", "ing"),
+    ("yong", "iong"),
+    ("yu", "ü"),
+    ("yue", "üe"),
+    ("yuan", "üan"),
+    ("yun", "ün"),
+    ("wu", "u"),
+    ("wa", "ua"),
+    ("wo", "uo"),
+    ("wai", "uai"),
+    ("wei", "ui"),
+    ("wan", "uan"),
+    ("wen", "un"),
+    ("wang", "uang"),
+    ("weng", "ueng"),
+    ("er", "er"),
+    ("r", "r"),
+];
+
+/// Tone marks of the second to fourth tone. The first tone is unmarked and
+/// the neutral tone is written before the syllable, see [`NEUTRAL_MARK`].
+const TONE_MARKS: [char; 3] = ['ˊ', 'ˇ', 'ˋ'];
+const NEUTRAL_MARK: char = '˙';
+
+/// Whether the char is a Bopomofo letter or tone mark.
+pub const fn is_zhuyin(c: char) -> bool {
+    matches!(c, 'ㄅ'..='ㄩ' | 'ˊ' | 'ˇ' | 'ˋ' | '˙')
+}
+
+/// Converts a pinyin syllable with tone marks (`lǜ`) or a tone number
+/// (`lv4`, `lu:4`) to zhuyin, e.g. `ㄌㄩˋ`. Syllables without a tone are
+/// neutral. Returns `None` if it is not a pinyin syllable.
+pub fn from_pinyin(syllable: &str) -> Option<String> {
+    let (letters, tone) = split_tone(syllable)?;
+    let initial = INITIALS
+        .iter()
+        .filter(|(initial, _)| letters.starts_with(initial))
+        .max_by_key(|(initial, _)| initial.len());
+    let (initial, final_) = match (ZERO_INITIAL.iter().find(|(s, _)| *s == letters), initial) {
+        (Some((_, final_)), _) => (None, (*final_).to_string()),
+        (None, Some((pinyin, zhuyin))) => {
+            let mut final_ = letters[pinyin.len()..].to_string();
+            if matches!(zhuyin, 'ㄐ' | 'ㄑ' | 'ㄒ') && final_.starts_with('u') {
+                final_ = final_.replacen('u', "ü", 1);
+            }
+            (Some(*zhuyin), final_)
+        }
+        // Finals that are spelled the same without an initial, e.g. `ài`
+        (None, None) => (None, letters),
+    };
+
+    let mut zhuyin = String::new();
+    if tone == 5 {
+        zhuyin.push(NEUTRAL_MARK);
+    }
+    zhuyin.extend(initial);
+    match (initial, final_.as_str()) {
+        (Some('ㄓ' | 'ㄔ' | 'ㄕ' | 'ㄖ' | 'ㄗ' | 'ㄘ' | 'ㄙ'), "i") => {}
+        (Some(_), "er") => return None,
+        _ => zhuyin.push_str(FINALS.iter().find(|(pinyin, _)| *pinyin == final_)?.1),
+    }
+    if matches!(tone, 2..=4) {
+        zhuyin.push(TONE_MARKS[usize::from(tone) - 2]);
+    }
+    Some(zhuyin)
+}
+
+/// Converts a zhuyin syllable to numbered pinyin, e.g. `ㄌㄩˋ` to `lü4`.
+/// Neutral syllables are left unnumbered like in [`BaseModel::tones`](super::models::BaseModel::tones).
+pub fn to_pinyin(syllable: &str) -> Option<String> {
+    let mut letters = syllable.trim().chars().collect::<Vec<_>>();
+    let mut tone = 1;
+    if letters.first() == Some(&NEUTRAL_MARK) {
+        letters.remove(0);
+        tone = 5;
+    } else if letters.last() == Some(&NEUTRAL_MARK) {
+        letters.pop();
+        tone = 5;
+    }
+    if let Some(i) = letters.iter().position(|c| TONE_MARKS.contains(c)) {
+        if tone == 5 || i + 1 != letters.len() {
+            return None;
+        }
+        tone = TONE_MARKS.iter().position(|c| *c == letters[i])? + 2;
+        letters.pop();
+    }
+
+    let initial = letters
+        .first()
+        .and_then(|c| INITIALS.iter().find(|(_, zhuyin)| zhuyin == c));
+    let rest = letters[usize::from(initial.is_some())..]
+        .iter()
+        .collect::<String>();
+    let final_ = if rest.is_empty() {
+        ""
+    } else {
+        FINALS.iter().find(|(_, zhuyin)| *zhuyin == rest)?.0
+    };
+
+    let pinyin = match (initial, final_) {
+        (None, "") => return None,
+        (None, "ong") => "weng".to_string(),
+        (None, final_) => ZERO_INITIAL
+            .iter()
+            .find(|(_, f)| *f == final_)
+            .map_or(final_, |(spelling, _)| spelling)
+            .to_string(),
+        (Some((pinyin, 'ㄓ' | 'ㄔ' | 'ㄕ' | 'ㄖ' | 'ㄗ' | 'ㄘ' | 'ㄙ')), "") => {
+            format!("{pinyin}i")
+        }
+        (Some(_), "" | "er" | "r") => return None,
+        (Some((pinyin, 'ㄐ' | 'ㄑ' | 'ㄒ')), final_) => {
+            if !final_.starts_with(['i', 'ü']) {
+                return None;
+            }
+            format!("{pinyin}{}", final_.replacen('ü', "u", 1))
+        }
+        (Some((pinyin, _)), final_) => format!("{pinyin}{final_}"),
+    };
+    Some(if tone == 5 {
+        pinyin
+    } else {
+        format!("{pinyin}{tone}")
+    })
+}
+
+/// Converts space separated pinyin to zhuyin, keeping what is not a syllable,
+/// e.g. `nǐ hǎo, lǜ` to `ㄋㄧˇ ㄏㄠˇ, ㄌㄩˋ`.
+pub fn from_pinyin_text(text: &str) -> String {
+    text.split(' ')
+        .map(|word| {
+            let syllable = word.trim_end_matches(|c: char| c.is_ascii_punctuation());
+            from_pinyin(syllable).map_or_else(
+                || word.to_string(),
+                |zhuyin| format!("{zhuyin}{}", &word[syllable.len()..]),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Leaves out the tone marks, e.g. `ㄋㄧˇ ㄏㄠˇ` to `ㄋㄧ ㄏㄠ`.
+pub fn without_tones(text: &str) -> String {
+    text.chars()
+        .filter(|c| !TONE_MARKS.contains(c) && *c != NEUTRAL_MARK)
+        .collect()
+}
+
+/// Converts a zhuyin answer to numbered pinyin, e.g. `ㄋㄧˇㄏㄠˇ` to
+/// `ni3 hao3`. Syllables may be run together as the tone marks and initials
+/// show where they start. Returns `None` if anything is not zhuyin.
+pub fn to_pinyin_text(text: &str) -> Option<String> {
+    split_syllables(text)?
+        .iter()
+        .map(|syllable| to_pinyin(syllable))
+        .collect::<Option<Vec<_>>>()
+        .map(|syllables| syllables.join(" "))
+}
+
+/// Splits zhuyin into syllables at spaces, after tone marks and wherever a
+/// letter cannot continue the syllable, e.g. a second initial.
+fn split_syllables(text: &str) -> Option<Vec<String>> {
+    // Position of a letter in a syllable: initial, medial, then rhyme
+    let slot = |c: char| match c {
+        'ㄅ'..='ㄙ' => Some(0),
+        'ㄧ' | 'ㄨ' | 'ㄩ' => Some(1),
+        'ㄚ'..='ㄦ' => Some(2),
+        _ => None,
+    };
+    let mut syllables = Vec::new();
+    let mut current = String::new();
+    let mut last_slot = None;
+    let mut finish = |current: &mut String, last_slot: &mut Option<u8>| {
+        if !current.is_empty() {
+            syllables.push(std::mem::take(current));
+        }
+        *last_slot = None;
+    };
+    for c in text.trim().chars() {
+        if c.is_whitespace() || matches!(c, ',' | '\'' | '，') {
+            finish(&mut current, &mut last_slot);
+        } else if TONE_MARKS.contains(&c) {
+            current.push(c);
+            finish(&mut current, &mut last_slot);
+        } else if c == NEUTRAL_MARK {
+            finish(&mut current, &mut last_slot);
+            current.push(c);
+        } else {
+            let slot = slot(c)?;
+            if last_slot.is_some_and(|last| slot <= last) {
+                finish(&mut current, &mut last_slot);
+            }
+            current.push(c);
+            last_slot = Some(slot);
+        }
+    }
+    finish(&mut current, &mut last_slot);
+    Some(syllables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_pinyin() {
+        assert_eq!(from_pinyin("nǐ").unwrap(), "ㄋㄧˇ");
+        assert_eq!(from_pinyin("hao3").unwrap(), "ㄏㄠˇ");
+        assert_eq!(from_pinyin("lǜ").unwrap(), "ㄌㄩˋ");
+        assert_eq!(from_pinyin("lv4").unwrap(), "ㄌㄩˋ");
+        assert_eq!(from_pinyin("lu:4").unwrap(), "ㄌㄩˋ");
+        assert_eq!(from_pinyin("lù").unwrap(), "ㄌㄨˋ");
+        assert_eq!(from_pinyin("xué").unwrap(), "ㄒㄩㄝˊ");
+        assert_eq!(from_pinyin("shì").unwrap(), "ㄕˋ");
+        assert_eq!(from_pinyin("zhōng").unwrap(), "ㄓㄨㄥ");
+        assert_eq!(from_pinyin("yǒu").unwrap(), "ㄧㄡˇ");
+        assert_eq!(from_pinyin("wèi").unwrap(), "ㄨㄟˋ");
+        assert_eq!(from_pinyin("yòng").unwrap(), "ㄩㄥˋ");
+        assert_eq!(from_pinyin("èr").unwrap(), "ㄦˋ");
+        assert_eq!(from_pinyin("ài").unwrap(), "ㄞˋ");
+        assert_eq!(from_pinyin("le").unwrap(), "˙ㄌㄜ");
+        assert_eq!(from_pinyin("r").unwrap(), "˙ㄦ");
+        assert_eq!(from_pinyin("hello"), None);
+        assert_eq!(from_pinyin("3"), None);
+        assert_eq!(
+            from_pinyin_text("nǐ hǎo, lǜ chá"),
+            "ㄋㄧˇ ㄏㄠˇ, ㄌㄩˋ ㄔㄚˊ"
+        );
+    }
+
+    #[test]
+    fn test_to_pinyin() {
+        assert_eq!(to_pinyin("ㄋㄧˇ").unwrap(), "ni3");
+        assert_eq!(to_pinyin("ㄌㄩˋ").unwrap(), "lü4");
+        assert_eq!(to_pinyin("ㄒㄩㄝˊ").unwrap(), "xue2");
+        assert_eq!(to_pinyin("ㄕˋ").unwrap(), "shi4");
+        assert_eq!(to_pinyin("ㄨㄥ").unwrap(), "weng1");
+        assert_eq!(to_pinyin("ㄉㄨㄥ").unwrap(), "dong1");
+        assert_eq!(to_pinyin("˙ㄌㄜ").unwrap(), "le");
+        assert_eq!(to_pinyin("ㄌㄜ˙").unwrap(), "le");
+        assert_eq!(to_pinyin("ㄐㄚ"), None);
+        assert_eq!(to_pinyin("ㄋˇㄧ"), None);
+        assert_eq!(to_pinyin_text("ㄋㄧˇㄏㄠˇ").unwrap(), "ni3 hao3");
+        assert_eq!(to_pinyin_text("ㄊㄧㄢㄑㄧˋ").unwrap(), "tian1 qi4");
+        assert_eq!(to_pinyin_text("ㄏㄠˇ ˙ㄇㄚ").unwrap(), "hao3 ma");
+        assert_eq!(to_pinyin_text("ni3"), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for syllable in [
+            "ba1", "zhi4", "ri4", "si3", "ju3", "qiong2", "xuan3", "nü3", "lüe4", "yi1", "ya2",
+            "ye4", "you3", "yin1", "ying2", "yu2", "yue4", "yuan2", "yun4", "wu3", "wo3", "wei4",
+            "wen2", "weng1", "er2", "a1", "ou1", "zhuang4", "gui4", "liu2", "dun4", "de",
+        ] {
+            let zhuyin = from_pinyin(syllable).unwrap();
+            assert_eq!(to_pinyin(&zhuyin).unwrap(), syllable, "{zhuyin}");
+        }
+    }
+}